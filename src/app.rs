@@ -1,22 +1,128 @@
+use crate::cgroup::{self, CgroupStats};
+use crate::diff::{self, DiffStatus, SnapshotDiff};
+use crate::lookup::{self, Lookup};
+use crate::stale;
+use crate::threads::{self, ThreadStack};
+use crate::timeline::Timeline;
 use crate::ui::{
-    HelpWidget, InfoWidget, LegendWidget, LogWidget, PathFilterWidget, PathListWidget,
-    SegmentTableWidget,
+    AddressSpaceWidget, CgroupWidget, CommandWidget, HelpWidget, InfoWidget, LegendWidget,
+    LogWidget, PagemapWidget, PathFilterWidget, PathListWidget, SegmentTableWidget, TimelineWidget,
+    TreeWidget, TreemapWidget,
 };
+use clap::ValueEnum;
+use humansize::{format_size, DECIMAL};
+use log::*;
 use procfs::process::MMapPath;
 use procfs::process::MMapPath::*;
-use procfs::process::MemoryMap;
-use procfs::ProcError;
+use procfs::process::{MemoryMap, MemoryMaps};
+use procfs::{FromBufRead, FromRead, ProcError};
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::error;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 pub type MemoryMapMatrix = Vec<Vec<MemoryMap>>;
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
+/// Smaps fields that describe a mapping rather than measure it, so adding
+/// them up across mappings means nothing.
+pub const UNSUMMABLE_FIELDS: [&str; 4] = [
+    "KernelPageSize",
+    "MMUPageSize",
+    "THPeligible",
+    "ProtectionKey",
+];
+
+/// A smaps field, or the number of segments, path groups can be ranked by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum Metric {
+    Rss,
+    Pss,
+    Size,
+    Swap,
+    PrivateDirty,
+    Segments,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Rss => "Rss",
+            Metric::Pss => "Pss",
+            Metric::Size => "Size",
+            Metric::Swap => "Swap",
+            Metric::PrivateDirty => "Private_Dirty",
+            Metric::Segments => "Segments",
+        }
+    }
+
+    /// The field summed over all mappings of a group.
+    pub fn sum(&self, group: &[MemoryMap]) -> u64 {
+        match self {
+            Metric::Segments => group.len() as u64,
+            _ => group
+                .iter()
+                .map(|mm| *mm.extension.map.get(self.name()).unwrap_or(&0))
+                .sum(),
+        }
+    }
+
+    pub fn format(&self, value: u64) -> String {
+        match self {
+            Metric::Segments => value.to_string(),
+            _ => format_size(value, DECIMAL),
+        }
+    }
+
+    /// The metric after this one, or none after the last one.
+    pub fn next(&self) -> Option<Metric> {
+        Metric::value_variants()
+            .iter()
+            .skip_while(|metric| *metric != self)
+            .nth(1)
+            .copied()
+    }
+}
+
 pub struct App {
     running: bool,
     pub debug: bool,
+    source: SmapsSource,
+    /// What the tab of this app is called.
+    pub name: String,
+    /// Re-read the smaps on every tick once this much time has passed.
+    pub refresh_interval: Option<Duration>,
+    last_refresh: Instant,
     pub selected_pane: AppSelectedPane,
+    /// The last capture read from the source.
+    snapshot: Rc<MemoryMapMatrix>,
+    /// When set, the widgets show `snapshot` compared against it.
+    baseline: Option<Rc<MemoryMapMatrix>>,
+    /// What the widgets are showing, either `snapshot` or its diff.
     pub memory_maps: Rc<MemoryMapMatrix>,
+    /// How `memory_maps` changed since the baseline, if there is one.
+    diff: Option<Rc<SnapshotDiff>>,
+    /// Set when replaying a recording.
+    pub timeline: Option<Timeline>,
+    /// Set when showing a cgroup, re-read with the smaps.
+    pub cgroup_stats: Option<CgroupStats>,
+    /// The threads of a live process and their stacks in `memory_maps`.
+    pub threads: Vec<ThreadStack>,
+    /// Order of the path groups, address order when unset.
+    pub sort: Option<Metric>,
+    pub grouping: Grouping,
+    /// Show the tree pane in place of the path list.
+    pub tree_view: bool,
+    /// Show the address space strip below the other panes.
+    pub address_space_view: bool,
+    /// Show the treemap in place of the segment table.
+    pub treemap_view: bool,
+    /// Show the pages of the selected segment below the other panes.
+    pub pagemap_view: bool,
     pub segment_list_widget: SegmentTableWidget,
     pub path_list_widget: PathListWidget,
     pub path_filter_widget: PathFilterWidget,
@@ -24,32 +130,247 @@ pub struct App {
     pub log_widget: LogWidget,
     pub legend_widget: LegendWidget,
     pub help_widget: HelpWidget,
+    pub timeline_widget: TimelineWidget,
+    pub tree_widget: TreeWidget,
+    pub command_widget: CommandWidget,
+    pub address_space_widget: AddressSpaceWidget,
+    pub treemap_widget: TreemapWidget,
+    pub pagemap_widget: PagemapWidget,
+    pub cgroup_widget: CgroupWidget,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AppSelectedPane {
     Segment,
     Path,
+    Tree,
+    AddressSpace,
+    Treemap,
 }
 
-impl App {
-    pub fn new(pid: i32, debug: bool) -> AppResult<Self> {
-        let process = procfs::process::Process::new(pid)?;
-        let memory_maps = match smaps_rollup(&process)? {
+/// How mappings are put into path groups.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Grouping {
+    /// Consecutive mappings with the same path.
+    #[default]
+    Contiguous,
+    /// Every mapping with the same path, wherever it sits.
+    Path,
+}
+
+impl Grouping {
+    /// Group the mappings of `memory_maps` again, keeping the rollup first.
+    pub fn regroup(&self, memory_maps: &MemoryMapMatrix) -> MemoryMapMatrix {
+        let (rollup, mut maps): (Vec<MemoryMap>, Vec<MemoryMap>) = memory_maps
+            .iter()
+            .flatten()
+            .cloned()
+            .partition(|mm| mm.pathname == Rollup);
+        maps.sort_by_key(|mm| mm.address);
+        let mut regrouped: MemoryMapMatrix = rollup.into_iter().map(|mm| vec![mm]).collect();
+        regrouped.extend(match self {
+            Grouping::Contiguous => merge_maps(maps),
+            Grouping::Path => merge_paths(maps),
+        });
+        regrouped
+    }
+
+    pub fn toggle(&self) -> Self {
+        match self {
+            Grouping::Contiguous => Grouping::Path,
+            Grouping::Path => Grouping::Contiguous,
+        }
+    }
+}
+
+/// Broad kind of a mapping, the top level of the tree view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Category {
+    FileBacked,
+    Anonymous,
+    Heap,
+    Stack,
+    Special,
+}
+
+impl Category {
+    pub fn of(path: &MMapPath) -> Self {
+        match path {
+            Path(_) => Category::FileBacked,
+            Anonymous => Category::Anonymous,
+            Heap => Category::Heap,
+            Stack | TStack(_) => Category::Stack,
+            // The mappings of one process of a cgroup, e.g. `heap (1234)`,
+            // see [`crate::cgroup::aggregate`].
+            Other(name) => match name.rsplit_once(" (").map(|(path, _)| path) {
+                Some("anonymous") => Category::Anonymous,
+                Some("heap") => Category::Heap,
+                Some(path) if path == "stack" || path.starts_with("thread stack: ") => {
+                    Category::Stack
+                }
+                _ => Category::Special,
+            },
+            _ => Category::Special,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::FileBacked => "file-backed",
+            Category::Anonymous => "anonymous",
+            Category::Heap => "heap",
+            Category::Stack => "stacks",
+            Category::Special => "special",
+        }
+    }
+}
+
+/// Where the smaps data shown by the [`App`] is read from.
+#[derive(Clone, Debug)]
+pub enum SmapsSource {
+    /// A running process, read from `/proc/<pid>/smaps`.
+    Pid(i32),
+    /// A saved copy of a `/proc/<pid>/smaps` file.
+    File(PathBuf),
+    /// Raw smaps text, e.g. piped in through stdin.
+    Text(String),
+    /// A file written by `record`. Loading it yields the newest snapshot.
+    Recording(PathBuf),
+    /// Every process of a cgroup directory, added up per path.
+    Cgroup(PathBuf),
+    /// One thread of a process, read from `/proc/<pid>/task/<tid>/smaps`.
+    Thread(i32, i32),
+}
+
+impl FromStr for SmapsSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(pid) = s.trim().parse::<i32>() {
+            return Ok(SmapsSource::Pid(pid));
+        }
+        // Anything spanning several lines is assumed to be a smaps dump.
+        if s.trim().contains('\n') {
+            return Ok(SmapsSource::Text(s.to_string()));
+        }
+        Err(format!("expected a pid or smaps text, got '{}'", s.trim()))
+    }
+}
+
+impl SmapsSource {
+    /// A short name for tabs: the comm and pid of a process, or the file name.
+    pub fn name(&self) -> String {
+        match self {
+            SmapsSource::Pid(pid) => {
+                match procfs::process::Process::new(*pid).and_then(|p| p.stat()) {
+                    Ok(stat) => format!("{} ({})", stat.comm, pid),
+                    Err(_) => pid.to_string(),
+                }
+            }
+            SmapsSource::File(path) | SmapsSource::Recording(path) | SmapsSource::Cgroup(path) => {
+                path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned()
+            }
+            SmapsSource::Text(_) => "stdin".to_string(),
+            SmapsSource::Thread(pid, tid) => {
+                let task = procfs::process::Process::new(*pid).and_then(|p| p.task_from_tid(*tid));
+                match task.and_then(|task| task.stat()) {
+                    Ok(stat) => format!("{} ({}/{})", stat.comm, pid, tid),
+                    Err(_) => format!("{}/{}", pid, tid),
+                }
+            }
+        }
+    }
+
+    /// The process read, for the sources that read a single one.
+    pub fn pid(&self) -> Option<i32> {
+        match self {
+            SmapsSource::Pid(pid) | SmapsSource::Thread(pid, _) => Some(*pid),
+            _ => None,
+        }
+    }
+
+    /// The directory the paths of a live source resolve in, the root of the
+    /// process or of the first process of a cgroup.
+    pub fn root(&self) -> Option<PathBuf> {
+        let pid = match self {
+            SmapsSource::Cgroup(path) => *cgroup::pids(path).ok()?.first()?,
+            source => source.pid()?,
+        };
+        Some(PathBuf::from(format!("/proc/{}/root", pid)))
+    }
+
+    /// Read the source and group it into a [`MemoryMapMatrix`]. The rollup
+    /// entry, if there is one, is always the first group.
+    pub fn load(&self) -> AppResult<MemoryMapMatrix> {
+        let (rollup, memory_maps) = match self {
+            SmapsSource::Pid(pid) => {
+                let process = procfs::process::Process::new(*pid)?;
+                (smaps_rollup(&process)?, smaps(&process)?)
+            }
+            SmapsSource::File(path) => split_rollup(MemoryMaps::from_file(path)?),
+            SmapsSource::Text(text) => split_rollup(MemoryMaps::from_buf_read(text.as_bytes())?),
+            SmapsSource::Recording(path) => return Timeline::load(path)?.last_memory_maps(),
+            SmapsSource::Cgroup(path) => return cgroup::load(path),
+            SmapsSource::Thread(pid, tid) => {
+                let task = format!("/proc/{}/task/{}", pid, tid);
+                let (rollup, _) =
+                    split_rollup(MemoryMaps::from_file(format!("{}/smaps_rollup", task))?);
+                let maps = MemoryMaps::from_file(format!("{}/smaps", task))?;
+                (rollup, merge_maps(maps.0))
+            }
+        };
+        Ok(match rollup {
             Some(v) => {
-                let mut rollup_prefix = vec![vec![v.clone()]];
-                let memory_maps = smaps(&process)?;
+                let mut rollup_prefix = vec![vec![v]];
                 rollup_prefix.extend(memory_maps);
-                Rc::new(rollup_prefix)
+                rollup_prefix
             }
-            None => Rc::new(smaps(&process)?),
+            None => memory_maps,
+        })
+    }
+}
+
+impl App {
+    pub fn new(source: SmapsSource, debug: bool) -> AppResult<Self> {
+        // Replays start at the first snapshot rather than the newest one.
+        let timeline = match &source {
+            SmapsSource::Recording(path) => Some(Timeline::load(path)?),
+            _ => None,
+        };
+        let memory_maps = match &timeline {
+            Some(timeline) => Rc::new(timeline.memory_maps()?),
+            None => Rc::new(source.load()?),
         };
 
-        Ok(Self {
+        let cgroup_stats = match &source {
+            SmapsSource::Cgroup(path) => Some(CgroupStats::read(path)),
+            _ => None,
+        };
+
+        let mut app = Self {
             running: true,
             debug,
+            name: source.name(),
+            source,
+            refresh_interval: None,
+            last_refresh: Instant::now(),
             selected_pane: AppSelectedPane::Path,
+            snapshot: Rc::clone(&memory_maps),
+            baseline: None,
             memory_maps: Rc::clone(&memory_maps),
+            diff: None,
+            timeline,
+            cgroup_stats,
+            threads: Vec::new(),
+            sort: None,
+            grouping: Grouping::default(),
+            tree_view: false,
+            address_space_view: false,
+            treemap_view: false,
+            pagemap_view: false,
             segment_list_widget: SegmentTableWidget::new(Rc::clone(&memory_maps)),
             path_list_widget: PathListWidget::new(Rc::clone(&memory_maps)),
             path_filter_widget: PathFilterWidget::default(),
@@ -57,12 +378,296 @@ impl App {
             log_widget: LogWidget::default(),
             legend_widget: LegendWidget::default(),
             help_widget: HelpWidget::default(),
-        })
+            timeline_widget: TimelineWidget::default(),
+            tree_widget: TreeWidget::new(&memory_maps),
+            command_widget: CommandWidget::default(),
+            address_space_widget: AddressSpaceWidget::new(Rc::clone(&memory_maps)),
+            treemap_widget: TreemapWidget::new(Rc::clone(&memory_maps), Metric::Pss),
+            pagemap_widget: PagemapWidget::default(),
+            cgroup_widget: CgroupWidget::default(),
+        };
+        app.annotate_paths(true);
+        Ok(app)
     }
 
     /// Handles the tick event of the terminal.
     pub fn tick(&mut self) {
-        self.path_list_widget.searcher.tick(10);
+        if let Some(interval) = self.refresh_interval {
+            if self.last_refresh.elapsed() >= interval {
+                self.refresh();
+            }
+        }
+        self.path_list_widget.tick();
+    }
+
+    /// Re-read the smaps from the source and update the widgets in place.
+    ///
+    /// A failed read (e.g. the process exited) is logged and the last good
+    /// data stays on screen.
+    pub fn refresh(&mut self) {
+        self.last_refresh = Instant::now();
+        if let SmapsSource::Cgroup(path) = &self.source {
+            self.cgroup_stats = Some(CgroupStats::read(path));
+        }
+        let memory_maps = match self.timeline.as_mut() {
+            Some(timeline) => timeline.reload().and_then(|_| timeline.memory_maps()),
+            None => self.source.load(),
+        };
+        match memory_maps {
+            Ok(v) => self.show(Rc::new(v)),
+            Err(e) => {
+                warn!(target:"App", "Refresh failed: {}", e);
+                return;
+            }
+        };
+        debug!(target:"App", "Refreshed {} paths", self.memory_maps.len());
+    }
+
+    /// Move through a replayed recording and show the snapshot there.
+    pub fn seek(&mut self, seek: fn(&mut Timeline)) {
+        let Some(timeline) = self.timeline.as_mut() else {
+            return;
+        };
+        seek(timeline);
+        match timeline.memory_maps() {
+            Ok(v) => self.show(Rc::new(v)),
+            Err(e) => warn!(target:"App", "Failed to read snapshot: {}", e),
+        }
+    }
+
+    /// Compare the current and all following captures against `baseline`.
+    pub fn set_baseline(&mut self, baseline: Rc<MemoryMapMatrix>) {
+        self.baseline = Some(baseline);
+        self.show(Rc::clone(&self.snapshot));
+    }
+
+    /// Use the capture on screen as the baseline for the following ones.
+    pub fn mark_baseline(&mut self) {
+        self.set_baseline(Rc::clone(&self.snapshot));
+        debug!(target:"App", "Marked baseline");
+    }
+
+    /// Rank the path groups by the next metric, back to address order after
+    /// the last one.
+    pub fn cycle_sort(&mut self) {
+        self.sort = match self.sort {
+            None => Some(Metric::Rss),
+            Some(metric) => metric.next(),
+        };
+        self.show(Rc::clone(&self.snapshot));
+    }
+
+    pub fn set_grouping(&mut self, grouping: Grouping) {
+        self.grouping = grouping;
+        self.show(Rc::clone(&self.snapshot));
+    }
+
+    /// Switch between contiguous and whole address space path groups.
+    pub fn toggle_grouping(&mut self) {
+        self.set_grouping(self.grouping.toggle());
+    }
+
+    /// Swap the path list for the tree pane and back.
+    pub fn toggle_tree(&mut self) {
+        self.tree_view = !self.tree_view;
+        if let AppSelectedPane::Path | AppSelectedPane::Tree = self.selected_pane {
+            self.focus(self.panes()[0]);
+        }
+    }
+
+    /// Move through the tree pane. When a segment or file ends up selected,
+    /// the path list and segment table follow it.
+    pub fn move_tree(&mut self, step: fn(&mut TreeWidget)) {
+        step(&mut self.tree_widget);
+        if let Some((group, segment)) = self.tree_widget.selected_segment() {
+            self.select_mapping(group, segment);
+        }
+    }
+
+    /// Show or hide the address space strip. Hiding it while it is the
+    /// active pane hands the focus back to the path list.
+    pub fn toggle_address_space(&mut self) {
+        self.address_space_view = !self.address_space_view;
+        if let AppSelectedPane::AddressSpace = self.selected_pane {
+            self.switch_pane();
+        }
+    }
+
+    /// Show or hide the pages of the selected segment.
+    pub fn toggle_pagemap(&mut self) {
+        self.pagemap_view = !self.pagemap_view;
+    }
+
+    /// Swap the segment table for the treemap and back.
+    pub fn toggle_treemap(&mut self) {
+        self.treemap_view = !self.treemap_view;
+        if let AppSelectedPane::Segment | AppSelectedPane::Treemap = self.selected_pane {
+            self.focus(self.panes()[1]);
+        }
+    }
+
+    /// Move through the treemap, the path list follows the selected group.
+    pub fn move_treemap(&mut self, step: fn(&mut TreemapWidget)) {
+        step(&mut self.treemap_widget);
+        if let Some(group) = self.treemap_widget.selected_group() {
+            self.path_list_widget.select_group(group);
+        }
+    }
+
+    /// Step through the mappings in address order on the strip.
+    pub fn move_address_space(&mut self, step: fn(&mut AddressSpaceWidget)) {
+        step(&mut self.address_space_widget);
+        if let Some((group, segment)) = self.address_space_widget.selected_mapping() {
+            self.select_mapping(group, segment);
+        }
+    }
+
+    fn select_mapping(&mut self, group: usize, segment: usize) {
+        self.path_list_widget.select_group(group);
+        self.segment_list_widget.select_segment(group, segment);
+    }
+
+    /// Run the command typed in the `:` prompt. The prompt closes unless
+    /// there is something to report.
+    pub fn run_command(&mut self) {
+        let input = self.command_widget.input.clone();
+        let result = match input.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["goto", address] => lookup::parse_address(address)
+                .map(|address| self.goto(address))
+                .map_err(|e| format!("'{}' is not an address: {}", address, e)),
+            [] => Ok(None),
+            _ => Err(format!("unknown command '{}', try goto <address>", input)),
+        };
+        match result {
+            Ok(None) => self.command_widget.toggle(),
+            Ok(Some(message)) => self.command_widget.message(message, false),
+            Err(message) => self.command_widget.message(message, true),
+        }
+    }
+
+    /// Select the path group and segment holding `address`. An unmapped
+    /// address selects the closest mapping and describes its neighbours.
+    /// Against a baseline, the segments that are gone are left out.
+    pub fn goto(&mut self, address: u64) -> Option<String> {
+        let diff = self.diff.clone();
+        let present = |(g, s): (usize, usize)| {
+            diff.as_ref()
+                .is_none_or(|diff| diff.segments[g][s].status != DiffStatus::Removed)
+        };
+        let (target, message) = match lookup::lookup_where(&self.memory_maps, address, present) {
+            Lookup::Mapped(g, s) => (Some((g, s)), None),
+            Lookup::Hole { below, above } => {
+                let mut message = format!("{:#x} is not mapped", address);
+                let below_distance = below.map(|(g, s)| {
+                    let mm = &self.memory_maps[g][s];
+                    message += &format!("\nbelow  {}", lookup::describe(mm));
+                    address - mm.address.1 + 1
+                });
+                let above_distance = above.map(|(g, s)| {
+                    let mm = &self.memory_maps[g][s];
+                    message += &format!("\nabove  {}", lookup::describe(mm));
+                    mm.address.0 - address
+                });
+                let nearest = match (below_distance, above_distance) {
+                    (Some(b), Some(a)) if a < b => above,
+                    (Some(_), _) => below,
+                    (None, _) => above,
+                };
+                (nearest, Some(message))
+            }
+        };
+        if let Some((group, segment)) = target {
+            // A filter could hide the group, so it has to go.
+            if !self.path_filter_widget.filter.is_empty() {
+                self.path_filter_widget.filter.clear();
+                self.path_list_widget.clear_filter();
+            }
+            self.select_mapping(group, segment);
+            self.tree_widget.select_segment(group, segment);
+        }
+        message
+    }
+
+    fn show(&mut self, snapshot: Rc<MemoryMapMatrix>) {
+        // Captures are always read in contiguous groups.
+        let regrouped = match self.grouping {
+            Grouping::Contiguous => Rc::clone(&snapshot),
+            grouping => Rc::new(grouping.regroup(&snapshot)),
+        };
+        let (mut memory_maps, mut diff) = match &self.baseline {
+            Some(baseline) => {
+                let baseline = self.grouping.regroup(baseline);
+                let (memory_maps, diff) = diff::diff(&baseline, &regrouped);
+                (Rc::new(memory_maps), Some(diff))
+            }
+            None => (regrouped, None),
+        };
+        if let Some(metric) = self.sort {
+            // The rollup stays on top, it would lead every ranking anyway.
+            let mut order: Vec<usize> = (0..memory_maps.len()).collect();
+            order.sort_by_key(|idx| {
+                let group = &memory_maps[*idx];
+                (group[0].pathname != Rollup, Reverse(metric.sum(group)))
+            });
+            memory_maps = Rc::new(order.iter().map(|idx| memory_maps[*idx].clone()).collect());
+            if let Some(diff) = diff.as_mut() {
+                diff.groups = order.iter().map(|idx| diff.groups[*idx]).collect();
+                diff.segments = order
+                    .iter()
+                    .map(|idx| diff.segments[*idx].clone())
+                    .collect();
+            }
+        }
+        let diff = diff.map(Rc::new);
+        // Sorting and grouping show the same capture again.
+        let captured = !Rc::ptr_eq(&snapshot, &self.snapshot);
+        self.snapshot = snapshot;
+        self.path_list_widget
+            .update(Rc::clone(&memory_maps), diff.clone(), self.sort);
+        self.segment_list_widget.update(
+            Rc::clone(&memory_maps),
+            diff.clone(),
+            self.path_list_widget.selected_identifiers(),
+        );
+        self.tree_widget.update(&memory_maps);
+        self.address_space_widget.update(Rc::clone(&memory_maps));
+        self.treemap_widget
+            .update(Rc::clone(&memory_maps), self.sort.unwrap_or(Metric::Pss));
+        self.memory_maps = memory_maps;
+        self.diff = diff;
+        self.pagemap_widget.invalidate();
+        self.annotate_paths(captured);
+    }
+
+    /// Flag the groups mapping deleted or replaced files in the path list,
+    /// and name the threads whose stacks they hold. The threads are only
+    /// read again with a new capture.
+    fn annotate_paths(&mut self, captured: bool) {
+        let root = self.source.root();
+        self.path_list_widget
+            .set_stale(stale::stale_groups(&self.memory_maps, root.as_deref()));
+        let Some(pid) = self.source.pid() else {
+            return;
+        };
+        if captured {
+            self.threads = threads::read_threads(pid).unwrap_or_else(|e| {
+                warn!(target:"App", "Failed to read the threads: {}", e);
+                Vec::new()
+            });
+        }
+        threads::locate_stacks(&mut self.threads, pid, &self.memory_maps);
+        self.path_list_widget
+            .set_thread_stacks(threads::stack_labels(&self.threads, self.memory_maps.len()));
+    }
+
+    /// The pid of the process shown, if this is a single live process.
+    pub fn pid(&self) -> Option<i32> {
+        self.source.pid()
+    }
+
+    /// The last capture read from the source, as it was read.
+    pub fn snapshot(&self) -> Rc<MemoryMapMatrix> {
+        Rc::clone(&self.snapshot)
     }
 
     /// Set running to false to quit the application.
@@ -74,23 +679,50 @@ impl App {
         self.running
     }
 
-    pub fn switch_pane(&mut self) {
-        match self.selected_pane {
-            AppSelectedPane::Segment => {
-                // Import to reset the Segment selection so you don't go
-                // out of bounds on a smaller segment as you navigate the
-                // Path pane.
-                self.segment_list_widget.active_pane(false);
-                self.segment_list_widget.reset_select();
-                self.selected_pane = AppSelectedPane::Path;
-                self.path_list_widget.active_pane(true);
-            }
-            AppSelectedPane::Path => {
-                self.path_list_widget.active_pane(false);
-                self.selected_pane = AppSelectedPane::Segment;
-                self.segment_list_widget.active_pane(true);
+    /// The panes tab cycles through, in order.
+    fn panes(&self) -> Vec<AppSelectedPane> {
+        let mut panes = vec![
+            match self.tree_view {
+                true => AppSelectedPane::Tree,
+                false => AppSelectedPane::Path,
+            },
+            match self.treemap_view {
+                true => AppSelectedPane::Treemap,
+                false => AppSelectedPane::Segment,
+            },
+        ];
+        if self.address_space_view {
+            panes.push(AppSelectedPane::AddressSpace);
+        }
+        panes
+    }
+
+    fn focus(&mut self, pane: AppSelectedPane) {
+        for (pane, active) in [(self.selected_pane, false), (pane, true)] {
+            match pane {
+                AppSelectedPane::Segment => self.segment_list_widget.active_pane(active),
+                AppSelectedPane::Path => self.path_list_widget.active_pane(active),
+                AppSelectedPane::Tree => self.tree_widget.active_pane(active),
+                AppSelectedPane::AddressSpace => self.address_space_widget.active_pane(active),
+                AppSelectedPane::Treemap => self.treemap_widget.active_pane(active),
             }
         }
+        self.selected_pane = pane;
+    }
+
+    pub fn switch_pane(&mut self) {
+        let panes = self.panes();
+        let next = panes
+            .iter()
+            .position(|pane| *pane == self.selected_pane)
+            .map_or(0, |idx| (idx + 1) % panes.len());
+        if let AppSelectedPane::Path | AppSelectedPane::Tree = panes[next] {
+            // Import to reset the Segment selection so you don't go
+            // out of bounds on a smaller segment as you navigate the
+            // Path pane.
+            self.segment_list_widget.reset_select();
+        }
+        self.focus(panes[next]);
     }
 }
 
@@ -100,8 +732,18 @@ fn smaps_rollup(process: &procfs::process::Process) -> Result<Option<MemoryMap>,
 }
 
 fn smaps(process: &procfs::process::Process) -> Result<MemoryMapMatrix, ProcError> {
-    let maps = process.smaps()?.0;
+    Ok(merge_maps(process.smaps()?.0))
+}
+
+/// Saved smaps text may carry a `[rollup]` entry (e.g. a smaps_rollup dump
+/// pasted in front of the smaps). Pull it out so it can lead the matrix.
+fn split_rollup(maps: MemoryMaps) -> (Option<MemoryMap>, MemoryMapMatrix) {
+    let (mut rollup, maps): (Vec<MemoryMap>, Vec<MemoryMap>) =
+        maps.into_iter().partition(|mm| mm.pathname == Rollup);
+    (rollup.pop(), merge_maps(maps))
+}
 
+fn merge_maps(maps: Vec<MemoryMap>) -> MemoryMapMatrix {
     // We want to merge consecutive memorymaps with the same name.
     // This allows us to create summaries and nested lists of maps.
     let mut merged: MemoryMapMatrix = Vec::new();
@@ -119,7 +761,24 @@ fn smaps(process: &procfs::process::Process) -> Result<MemoryMapMatrix, ProcErro
         }
         merged.push(map_group);
     }
-    Ok(merged)
+    merged
+}
+
+fn merge_paths(maps: Vec<MemoryMap>) -> MemoryMapMatrix {
+    // Same as merge_maps, but a path joins its group wherever it shows up in
+    // the address space. Groups are ordered by their first mapping.
+    let mut merged: MemoryMapMatrix = Vec::new();
+    let mut groups: HashMap<MMapPath, usize> = HashMap::new();
+    for mm in maps {
+        match groups.get(&mm.pathname) {
+            Some(idx) => merged[*idx].push(mm),
+            None => {
+                groups.insert(mm.pathname.clone(), merged.len());
+                merged.push(vec![mm]);
+            }
+        }
+    }
+    merged
 }
 
 pub fn mmpath_to_string(name: &MMapPath) -> String {
@@ -137,3 +796,62 @@ pub fn mmpath_to_string(name: &MMapPath) -> String {
         Other(x) => x.to_string(),
     }
 }
+
+/// The two letter flag names as printed in `/proc/<pid>/smaps`.
+pub fn vm_flag_names(mm: &MemoryMap) -> Vec<String> {
+    mm.extension
+        .vm_flags
+        .iter_names()
+        .map(|v| v.0.to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// libc split by an anonymous mapping, with a second one after it. smaps
+    /// pads the missing path of anonymous mappings with spaces.
+    const SMAPS: &str = "\
+55d000000000-7ffd00000000 ---p 00000000 00:00 0 [rollup]
+Rss: 16 kB
+7f0000000000-7f0000002000 r-xp 00001000 fd:01 1234 /usr/lib/libc.so.6
+Rss: 8 kB
+7f0000002000-7f0000003000 rw-p 00000000 00:00 0\x20
+Rss: 4 kB
+7f0000003000-7f0000004000 rw-p 00003000 fd:01 1234 /usr/lib/libc.so.6
+Rss: 4 kB
+7f0000004000-7f0000005000 rw-p 00000000 00:00 0\x20
+Rss: 0 kB
+";
+
+    fn layout(memory_maps: &MemoryMapMatrix) -> Vec<(String, Vec<u64>)> {
+        memory_maps
+            .iter()
+            .map(|group| {
+                let starts = group.iter().map(|mm| mm.address.0 & 0xffff).collect();
+                (mmpath_to_string(&group[0].pathname), starts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn regroup_by_path_merges_non_contiguous_groups() {
+        let memory_maps = SmapsSource::Text(SMAPS.to_string()).load().unwrap();
+        let contiguous = layout(&memory_maps);
+        assert_eq!(contiguous.len(), 5);
+
+        let by_path = Grouping::Path.regroup(&memory_maps);
+        assert_eq!(
+            layout(&by_path),
+            [
+                ("rollup".to_string(), vec![0]),
+                ("/usr/lib/libc.so.6".to_string(), vec![0, 0x3000]),
+                ("anonymous".to_string(), vec![0x2000, 0x4000]),
+            ]
+        );
+
+        let back = Grouping::Contiguous.regroup(&by_path);
+        assert_eq!(layout(&back), contiguous);
+    }
+}
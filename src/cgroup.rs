@@ -0,0 +1,352 @@
+use crate::app::{mmpath_to_string, AppResult, MemoryMapMatrix, SmapsSource};
+use log::*;
+use procfs::process::MMapPath::{self, Rollup};
+use procfs::process::MemoryMap;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where cgroup hierarchies are mounted: the unified (v2) one first, then the
+/// v1 memory controller and the hybrid mounts of systemd.
+const CGROUP_ROOTS: [&str; 4] = [
+    "/sys/fs/cgroup",
+    "/sys/fs/cgroup/memory",
+    "/sys/fs/cgroup/unified",
+    "/sys/fs/cgroup/systemd",
+];
+
+/// memory.stat entries worth showing next to the smaps totals, v2 names
+/// first and then their v1 counterparts. Missing ones are skipped.
+const MEMORY_STAT_KEYS: [&str; 13] = [
+    "anon",
+    "file",
+    "file_mapped",
+    "shmem",
+    "kernel",
+    "kernel_stack",
+    "pagetables",
+    "slab",
+    "sock",
+    "rss",
+    "cache",
+    "mapped_file",
+    "swap",
+];
+
+/// Smaps fields that are a share of the process or private to it, so they
+/// add up across processes. Every other field describes pages the processes
+/// may share, and the largest value is kept.
+const ADDITIVE_FIELDS: [&str; 5] = ["Anonymous", "AnonHugePages", "LazyFree", "Swap", "SwapPss"];
+
+/// Find the directory of `cgroup`, either a path to it or a path relative
+/// to the cgroup mounts, e.g. `system.slice/nginx.service`. With v1
+/// controllers, the one holding the memory accounting is preferred.
+pub fn resolve(cgroup: &Path) -> AppResult<PathBuf> {
+    let relative = cgroup
+        .strip_prefix("/sys/fs/cgroup")
+        .or_else(|_| cgroup.strip_prefix("/"))
+        .unwrap_or(cgroup);
+    let candidates: Vec<PathBuf> = CGROUP_ROOTS
+        .iter()
+        .map(|root| Path::new(root).join(relative))
+        .filter(|dir| dir.join("cgroup.procs").exists())
+        .collect();
+    candidates
+        .iter()
+        .find(|dir| memory_current(dir).is_some())
+        .or(candidates.first())
+        .cloned()
+        .ok_or_else(|| format!("no cgroup {}", cgroup.display()).into())
+}
+
+/// Find the cgroup of a systemd unit, `.service` is assumed without a suffix.
+pub fn find_unit(unit: &str) -> AppResult<PathBuf> {
+    let unit = match unit.contains('.') {
+        true => unit.to_string(),
+        false => format!("{}.service", unit),
+    };
+    let Some((root, dir)) = CGROUP_ROOTS
+        .iter()
+        .find_map(|root| Some((root, find_dir(Path::new(root), &unit)?)))
+    else {
+        return Err(format!("no cgroup for unit {}", unit).into());
+    };
+    // With v1 controllers the unit may turn up under one without memory
+    // accounting first, its processes tell where their memory is accounted.
+    if memory_current(&dir).is_none() {
+        let memory_dir = pids(&dir)?.first().and_then(|pid| memory_cgroup(*pid));
+        let unit_dir = memory_dir.as_deref().and_then(|dir| {
+            dir.ancestors()
+                .find(|dir| dir.file_name() == Some(unit.as_ref()))
+        });
+        if let Some(unit_dir) = unit_dir.filter(|dir| memory_current(dir).is_some()) {
+            return Ok(unit_dir.to_path_buf());
+        }
+    }
+    resolve(dir.strip_prefix(root)?)
+}
+
+/// The cgroup whose memory `pid` is accounted to, from `/proc/<pid>/cgroup`:
+/// the hierarchy of the v1 memory controller, or else the unified one.
+fn memory_cgroup(pid: i32) -> Option<PathBuf> {
+    let cgroups = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    // Lines read `<id>:<controllers>:<path>`, with no controllers for v2.
+    let hierarchy = |memory: bool| {
+        cgroups.lines().find_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+            let found = match memory {
+                true => controllers.split(',').any(|c| c == "memory"),
+                false => controllers.is_empty(),
+            };
+            found.then_some(path)
+        })
+    };
+    let (root, path) = match hierarchy(true) {
+        Some(path) => ("/sys/fs/cgroup/memory", path),
+        None => ("/sys/fs/cgroup", hierarchy(false)?),
+    };
+    Some(Path::new(root).join(path.trim_start_matches('/')))
+}
+
+fn find_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    let entries = fs::read_dir(dir).ok()?;
+    let subdirs: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+        .collect();
+    if let Some(found) = subdirs
+        .iter()
+        .find(|path| path.file_name() == Some(name.as_ref()))
+    {
+        return Some(found.clone());
+    }
+    subdirs.iter().find_map(|path| find_dir(path, name))
+}
+
+/// The processes in `cgroup` and in the cgroups below it, which memory.current
+/// and memory.stat account for as well.
+pub fn pids(cgroup: &Path) -> AppResult<Vec<i32>> {
+    let procs = fs::read_to_string(cgroup.join("cgroup.procs"))?;
+    let mut members: Vec<i32> = procs
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect();
+    for entry in fs::read_dir(cgroup)?.flatten() {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            // A child cgroup removed meanwhile has no processes left.
+            members.extend(pids(&entry.path()).unwrap_or_default());
+        }
+    }
+    Ok(members)
+}
+
+/// Read the smaps of every process in `cgroup` and add them up per path,
+/// see [`aggregate`]. Processes that exit or can't be read are skipped.
+pub fn load(cgroup: &Path) -> AppResult<MemoryMapMatrix> {
+    let matrices: Vec<(i32, MemoryMapMatrix)> = pids(cgroup)?
+        .into_iter()
+        .filter_map(|pid| match SmapsSource::Pid(pid).load() {
+            Ok(memory_maps) => Some((pid, memory_maps)),
+            Err(e) => {
+                warn!(target:"App", "Skipped pid {} of the cgroup: {}", pid, e);
+                None
+            }
+        })
+        .collect();
+    if matrices.is_empty() {
+        return Err(format!("no readable process in {}", cgroup.display()).into());
+    }
+    Ok(aggregate(&matrices))
+}
+
+/// dev, inode, offset, size and permissions of a file mapping.
+type FileRange = ((i32, i32), u64, u64, u64, u8);
+
+/// Put the mappings of several processes, by pid, together, one group per
+/// path.
+///
+/// The rollup is summed up from the merged mappings. File mappings are told
+/// apart by dev:inode rather than path, and the mappings of the same file
+/// range in several processes are merged into one: shares and private pages
+/// are added up, while the pages they share count once. Anonymous memory,
+/// heaps and stacks belong to their process and are kept apart, in a group
+/// named after it, e.g. `heap (1234)`: forked processes map them at the same
+/// addresses.
+pub fn aggregate(matrices: &[(i32, MemoryMapMatrix)]) -> MemoryMapMatrix {
+    let mut rollup: Option<MemoryMap> = None;
+    let mut merged: MemoryMapMatrix = Vec::new();
+    let mut groups: HashMap<String, usize> = HashMap::new();
+    let mut segments: HashMap<FileRange, (usize, usize)> = HashMap::new();
+    let mappings = matrices
+        .iter()
+        .flat_map(|(pid, matrix)| matrix.iter().flatten().map(move |mm| (*pid, mm)));
+    for (pid, mm) in mappings {
+        if mm.pathname == Rollup {
+            match rollup.as_mut() {
+                Some(rollup) => add_fields(rollup, mm, |_| true),
+                None => rollup = Some(mm.clone()),
+            }
+            continue;
+        }
+        let file = matches!(mm.pathname, MMapPath::Path(_)) && mm.inode != 0;
+        let mut mm = mm.clone();
+        if !file {
+            let path = format!("{} ({})", mmpath_to_string(&mm.pathname), pid);
+            mm.pathname = MMapPath::Other(path);
+        }
+        let key = match file {
+            true => format!("{}:{}:{}", mm.dev.0, mm.dev.1, mm.inode),
+            false => mmpath_to_string(&mm.pathname),
+        };
+        let group = *groups.entry(key).or_insert_with(|| {
+            merged.push(Vec::new());
+            merged.len() - 1
+        });
+        let range: FileRange = (
+            mm.dev,
+            mm.inode,
+            mm.offset,
+            mm.address.1 - mm.address.0,
+            mm.perms.bits(),
+        );
+        match segments.get(&range) {
+            Some((g, s)) if file => add_fields(&mut merged[*g][*s], &mm, is_additive),
+            _ => {
+                if file {
+                    segments.insert(range, (group, merged[group].len()));
+                }
+                merged[group].push(mm);
+            }
+        }
+    }
+    // The rollups count the pages the processes share once per process,
+    // the fields the mappings have are summed up from the groups instead.
+    // Only the shares, e.g. Pss_Anon, are left to the rollups.
+    if let Some(rollup) = rollup.as_mut() {
+        for (field, total) in rollup.extension.map.iter_mut() {
+            let values = merged
+                .iter()
+                .flatten()
+                .filter_map(|mm| mm.extension.map.get(field));
+            let mut values = values.peekable();
+            if values.peek().is_some() {
+                *total = values.sum();
+            }
+        }
+    }
+    let mut aggregated: MemoryMapMatrix = rollup.into_iter().map(|mm| vec![mm]).collect();
+    aggregated.extend(merged);
+    aggregated
+}
+
+fn is_additive(field: &str) -> bool {
+    field.starts_with("Pss") || field.starts_with("Private") || ADDITIVE_FIELDS.contains(&field)
+}
+
+/// Fold the fields of `mm` into `into`, adding up the `additive` ones and
+/// keeping the largest value of the others.
+fn add_fields(into: &mut MemoryMap, mm: &MemoryMap, additive: fn(&str) -> bool) {
+    for (field, value) in mm.extension.map.iter() {
+        let total = into.extension.map.entry(field.clone()).or_insert(0);
+        *total = match additive(field) {
+            true => *total + value,
+            false => (*total).max(*value),
+        };
+    }
+}
+
+/// What the kernel accounts to a cgroup, to hold against the smaps totals.
+#[derive(Clone, Debug, Default)]
+pub struct CgroupStats {
+    pub processes: usize,
+    /// memory.current, or memory.usage_in_bytes with cgroup v1.
+    pub memory_current: Option<u64>,
+    pub memory_stat: Vec<(String, u64)>,
+}
+
+impl CgroupStats {
+    pub fn read(cgroup: &Path) -> Self {
+        let stat = fs::read_to_string(cgroup.join("memory.stat")).unwrap_or_default();
+        let stat: HashMap<&str, u64> = stat
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once(' ')?;
+                Some((key, value.trim().parse().ok()?))
+            })
+            .collect();
+        Self {
+            processes: pids(cgroup).map(|pids| pids.len()).unwrap_or(0),
+            memory_current: memory_current(cgroup),
+            memory_stat: MEMORY_STAT_KEYS
+                .iter()
+                .filter_map(|key| Some((key.to_string(), *stat.get(key)?)))
+                .collect(),
+        }
+    }
+}
+
+fn memory_current(cgroup: &Path) -> Option<u64> {
+    ["memory.current", "memory.usage_in_bytes"]
+        .iter()
+        .find_map(|file| fs::read_to_string(cgroup.join(file)).ok())
+        .and_then(|text| text.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{Category, Metric};
+
+    /// A forked worker: libc and the heap at the same addresses as its
+    /// parent.
+    fn process(rss: u64, pss: u64, heap: u64) -> MemoryMapMatrix {
+        let smaps = format!(
+            "\
+55d000000000-7ffd00000000 ---p 00000000 00:00 0 [rollup]
+Rss: {rollup} kB
+Pss: {rollup_pss} kB
+Pss_Anon: {heap} kB
+7f0000000000-7f0000002000 r-xp 00001000 fd:01 1234 /usr/lib/libc.so.6
+Size: 8 kB
+Rss: {rss} kB
+Pss: {pss} kB
+55d000000000-55d000021000 rw-p 00000000 00:00 0 [heap]
+Size: 132 kB
+Rss: {heap} kB
+Pss: {heap} kB
+",
+            rollup = rss + heap,
+            rollup_pss = pss + heap,
+        );
+        SmapsSource::Text(smaps).load().unwrap()
+    }
+
+    #[test]
+    fn aggregate_keeps_heaps_apart_and_merges_files() {
+        let aggregated = aggregate(&[(1, process(8, 4, 16)), (2, process(4, 4, 32))]);
+        let paths: Vec<String> = aggregated
+            .iter()
+            .map(|group| mmpath_to_string(&group[0].pathname))
+            .collect();
+        assert_eq!(
+            paths,
+            ["rollup", "/usr/lib/libc.so.6", "heap (1)", "heap (2)"]
+        );
+        assert!(aggregated.iter().all(|group| group.len() == 1));
+        assert_eq!(Category::of(&aggregated[2][0].pathname), Category::Heap);
+
+        // The shared pages of libc count once, its shares add up.
+        let libc = &aggregated[1];
+        assert_eq!(Metric::Rss.sum(libc), 8 << 10);
+        assert_eq!(Metric::Pss.sum(libc), 8 << 10);
+
+        // The rollup adds up the groups rather than the rollups, which count
+        // libc twice.
+        let rollup = &aggregated[0][0].extension.map;
+        assert_eq!(rollup["Rss"], (8 + 16 + 32) << 10);
+        assert_eq!(rollup["Pss"], (8 + 16 + 32) << 10);
+        assert_eq!(rollup["Pss_Anon"], (16 + 32) << 10);
+    }
+}
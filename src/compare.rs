@@ -0,0 +1,144 @@
+use crate::app::{mmpath_to_string, Grouping, MemoryMapMatrix};
+use crate::diff::DiffStatus;
+use procfs::process::MMapPath::Rollup;
+use procfs::process::MemoryMap;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Rss, Pss and private memory of a path group.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Totals {
+    pub rss: u64,
+    pub pss: u64,
+    /// Private_Clean and Private_Dirty added up.
+    pub private: u64,
+}
+
+impl Totals {
+    fn of(group: &[MemoryMap]) -> Self {
+        let sum = |key: &str| -> u64 {
+            group
+                .iter()
+                .map(|mm| *mm.extension.map.get(key).unwrap_or(&0))
+                .sum()
+        };
+        Self {
+            rss: sum("Rss"),
+            pss: sum("Pss"),
+            private: sum("Private_Clean") + sum("Private_Dirty"),
+        }
+    }
+}
+
+/// A path of either process, with its group index and totals on each side.
+#[derive(Clone, Debug)]
+pub struct PathComparison {
+    pub path: String,
+    /// [`DiffStatus::Removed`] when only the left process maps the path,
+    /// [`DiffStatus::Added`] when only the right one does.
+    pub status: DiffStatus,
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub left_totals: Totals,
+    pub right_totals: Totals,
+}
+
+impl PathComparison {
+    pub fn rss_delta(&self) -> i64 {
+        self.right_totals.rss as i64 - self.left_totals.rss as i64
+    }
+
+    pub fn pss_delta(&self) -> i64 {
+        self.right_totals.pss as i64 - self.left_totals.pss as i64
+    }
+
+    pub fn private_delta(&self) -> i64 {
+        self.right_totals.private as i64 - self.left_totals.private as i64
+    }
+}
+
+/// Two processes with their mappings grouped by path, and the paths of both
+/// lined up.
+#[derive(Clone, Debug, Default)]
+pub struct Comparison {
+    pub left: MemoryMapMatrix,
+    pub right: MemoryMapMatrix,
+    /// The rollup first, then the largest Pss difference first.
+    pub paths: Vec<PathComparison>,
+}
+
+/// Line up the paths of two processes, e.g. two replicas of a service or a
+/// process before and after it forked.
+///
+/// Mappings are matched on their path alone, as the same library sits at a
+/// different address in every process.
+pub fn compare(left: &MemoryMapMatrix, right: &MemoryMapMatrix) -> Comparison {
+    let left = Grouping::Path.regroup(left);
+    let right = Grouping::Path.regroup(right);
+    let right_paths: HashMap<String, usize> = right
+        .iter()
+        .enumerate()
+        .map(|(idx, group)| (mmpath_to_string(&group[0].pathname), idx))
+        .collect();
+
+    let mut claimed = vec![false; right.len()];
+    let mut paths = Vec::new();
+    for (idx, group) in left.iter().enumerate() {
+        let path = mmpath_to_string(&group[0].pathname);
+        let found = right_paths.get(&path).copied();
+        if let Some(found) = found {
+            claimed[found] = true;
+        }
+        let right_totals = found.map(|v| Totals::of(&right[v])).unwrap_or_default();
+        paths.push(PathComparison {
+            path,
+            status: match found {
+                Some(_) => DiffStatus::Changed,
+                None => DiffStatus::Removed,
+            },
+            left: Some(idx),
+            right: found,
+            left_totals: Totals::of(group),
+            right_totals,
+        });
+    }
+    for (idx, group) in right.iter().enumerate() {
+        if claimed[idx] {
+            continue;
+        }
+        paths.push(PathComparison {
+            path: mmpath_to_string(&group[0].pathname),
+            status: DiffStatus::Added,
+            left: None,
+            right: Some(idx),
+            left_totals: Totals::default(),
+            right_totals: Totals::of(group),
+        });
+    }
+    for path in paths.iter_mut() {
+        if path.status == DiffStatus::Changed
+            && path.rss_delta() == 0
+            && path.pss_delta() == 0
+            && path.private_delta() == 0
+        {
+            path.status = DiffStatus::Unchanged;
+        }
+    }
+
+    let is_rollup = |path: &PathComparison| {
+        let group = match (path.left, path.right) {
+            (Some(idx), _) => &left[idx],
+            (None, Some(idx)) => &right[idx],
+            (None, None) => return false,
+        };
+        group[0].pathname == Rollup
+    };
+    paths.sort_by_cached_key(|path| {
+        (
+            !is_rollup(path),
+            Reverse(path.pss_delta().unsigned_abs()),
+            path.path.clone(),
+        )
+    });
+    Comparison { left, right, paths }
+}
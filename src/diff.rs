@@ -0,0 +1,279 @@
+use crate::app::{mmpath_to_string, MemoryMapMatrix};
+use procfs::process::MMapPath::Rollup;
+use procfs::process::MemoryMap;
+
+/// How a path group or segment changed between two smaps captures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffStatus {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+impl DiffStatus {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            DiffStatus::Added => "+",
+            DiffStatus::Removed => "-",
+            DiffStatus::Changed => "~",
+            DiffStatus::Unchanged => " ",
+        }
+    }
+}
+
+/// Change of a path group or segment, current minus baseline.
+#[derive(Clone, Copy, Debug)]
+pub struct Delta {
+    pub status: DiffStatus,
+    pub rss: i64,
+    pub pss: i64,
+    pub swap: i64,
+}
+
+impl Delta {
+    fn between(baseline: &[MemoryMap], current: &[MemoryMap], status: DiffStatus) -> Self {
+        let delta = |key| sum(current, key) - sum(baseline, key);
+        Self {
+            status,
+            rss: delta("Rss"),
+            pss: delta("Pss"),
+            swap: delta("Swap"),
+        }
+    }
+}
+
+/// Per group and per segment deltas, indexed like the [`MemoryMapMatrix`]
+/// returned alongside them by [`diff`].
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotDiff {
+    pub groups: Vec<Delta>,
+    pub segments: Vec<Vec<Delta>>,
+}
+
+/// Compare two captures of the same process.
+///
+/// Groups are matched on their path and overlapping address ranges, segments
+/// on their exact address range. The returned matrix holds the current
+/// groups followed by the groups only found in the baseline. Segments that
+/// disappeared from a group are merged back into it in address order, so
+/// removed entries can be browsed like any other.
+pub fn diff(
+    baseline: &MemoryMapMatrix,
+    current: &MemoryMapMatrix,
+) -> (MemoryMapMatrix, SnapshotDiff) {
+    let mut memory_maps = Vec::new();
+    let mut snapshot_diff = SnapshotDiff::default();
+    let mut claimed = vec![false; baseline.len()];
+
+    for group in current.iter() {
+        let found = baseline
+            .iter()
+            .enumerate()
+            .position(|(idx, base)| !claimed[idx] && same_group(base, group));
+        let (segments, deltas) = match found {
+            Some(idx) => {
+                claimed[idx] = true;
+                diff_segments(&baseline[idx], group)
+            }
+            None => {
+                let deltas = group
+                    .iter()
+                    .map(|mm| Delta::between(&[], std::slice::from_ref(mm), DiffStatus::Added))
+                    .collect();
+                (group.clone(), deltas)
+            }
+        };
+        let status = match found {
+            None => DiffStatus::Added,
+            Some(_) if deltas.iter().any(|d| d.status != DiffStatus::Unchanged) => {
+                DiffStatus::Changed
+            }
+            Some(_) => DiffStatus::Unchanged,
+        };
+        let base_group = found.map(|idx| baseline[idx].as_slice()).unwrap_or(&[]);
+        snapshot_diff
+            .groups
+            .push(Delta::between(base_group, group, status));
+        snapshot_diff.segments.push(deltas);
+        memory_maps.push(segments);
+    }
+
+    for (idx, group) in baseline.iter().enumerate() {
+        if claimed[idx] {
+            continue;
+        }
+        snapshot_diff
+            .groups
+            .push(Delta::between(group, &[], DiffStatus::Removed));
+        snapshot_diff.segments.push(
+            group
+                .iter()
+                .map(|mm| Delta::between(std::slice::from_ref(mm), &[], DiffStatus::Removed))
+                .collect(),
+        );
+        memory_maps.push(group.clone());
+    }
+
+    (memory_maps, snapshot_diff)
+}
+
+fn diff_segments(baseline: &[MemoryMap], current: &[MemoryMap]) -> (Vec<MemoryMap>, Vec<Delta>) {
+    let mut claimed = vec![false; baseline.len()];
+    let mut segments: Vec<(MemoryMap, Delta)> = Vec::new();
+
+    for mm in current.iter() {
+        let found = baseline
+            .iter()
+            .enumerate()
+            .position(|(idx, base)| !claimed[idx] && same_segment(base, mm));
+        let delta = match found {
+            Some(idx) => {
+                claimed[idx] = true;
+                let base = &baseline[idx];
+                let status = if base.perms == mm.perms && fields_equal(base, mm) {
+                    DiffStatus::Unchanged
+                } else {
+                    DiffStatus::Changed
+                };
+                Delta::between(std::slice::from_ref(base), std::slice::from_ref(mm), status)
+            }
+            None => Delta::between(&[], std::slice::from_ref(mm), DiffStatus::Added),
+        };
+        segments.push((mm.clone(), delta));
+    }
+
+    for (idx, base) in baseline.iter().enumerate() {
+        if !claimed[idx] {
+            let delta = Delta::between(std::slice::from_ref(base), &[], DiffStatus::Removed);
+            segments.push((base.clone(), delta));
+        }
+    }
+
+    segments.sort_by_key(|(mm, _)| mm.address);
+    segments.into_iter().unzip()
+}
+
+fn same_group(a: &[MemoryMap], b: &[MemoryMap]) -> bool {
+    let (a_start, a_end) = (a[0].address.0, a[a.len() - 1].address.1);
+    let (b_start, b_end) = (b[0].address.0, b[b.len() - 1].address.1);
+    mmpath_to_string(&a[0].pathname) == mmpath_to_string(&b[0].pathname)
+        && a_start < b_end
+        && b_start < a_end
+}
+
+fn same_segment(a: &MemoryMap, b: &MemoryMap) -> bool {
+    // The rollup spans the whole address space, which moves as the process
+    // maps and unmaps memory. There is only one, so match it on its path.
+    a.address == b.address || (a.pathname == Rollup && b.pathname == Rollup)
+}
+
+fn fields_equal(a: &MemoryMap, b: &MemoryMap) -> bool {
+    ["Size", "Rss", "Pss", "Swap"]
+        .iter()
+        .all(|key| a.extension.map.get(*key) == b.extension.map.get(*key))
+}
+
+fn sum(maps: &[MemoryMap], key: &str) -> i64 {
+    maps.iter()
+        .map(|mm| *mm.extension.map.get(key).unwrap_or(&0) as i64)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::SmapsSource;
+
+    fn capture(heap_end: &str, heap_rss: u64, lib: &str) -> MemoryMapMatrix {
+        let smaps = format!(
+            "\
+55d000000000-7ffd00000000 ---p 00000000 00:00 0 [rollup]
+Rss: {rollup} kB
+Pss: {rollup} kB
+55d000000000-{heap_end} rw-p 00000000 00:00 0 [heap]
+Size: 132 kB
+Rss: {heap_rss} kB
+Pss: {heap_rss} kB
+7f0000000000-7f0000002000 r-xp 00001000 fd:01 1234 /usr/lib/libc.so.6
+Size: 8 kB
+Rss: 8 kB
+Pss: 4 kB
+7f1000000000-7f1000001000 r-xp 00000000 fd:01 5678 {lib}
+Size: 4 kB
+Rss: 4 kB
+Pss: 4 kB
+",
+            rollup = heap_rss + 12,
+        );
+        SmapsSource::Text(smaps).load().unwrap()
+    }
+
+    #[test]
+    fn diff_matches_groups_and_segments() {
+        let baseline = capture("55d000021000", 16, "/usr/lib/libm.so.6");
+        let current = capture("55d000042000", 32, "/usr/lib/libz.so.1");
+        let (memory_maps, snapshot_diff) = diff(&baseline, &current);
+
+        let paths: Vec<String> = memory_maps
+            .iter()
+            .map(|group| mmpath_to_string(&group[0].pathname))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "rollup",
+                "heap",
+                "/usr/lib/libc.so.6",
+                "/usr/lib/libz.so.1",
+                "/usr/lib/libm.so.6",
+            ]
+        );
+        let statuses: Vec<DiffStatus> = snapshot_diff.groups.iter().map(|d| d.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                DiffStatus::Changed,
+                DiffStatus::Changed,
+                DiffStatus::Unchanged,
+                DiffStatus::Added,
+                DiffStatus::Removed,
+            ]
+        );
+
+        // The grown heap overlaps its old range, so it stays one group, but
+        // its segment no longer has the same address.
+        let heap = &snapshot_diff.groups[1];
+        assert_eq!((heap.rss, heap.pss, heap.swap), (16 << 10, 16 << 10, 0));
+        let addresses: Vec<(u64, u64)> = memory_maps[1].iter().map(|mm| mm.address).collect();
+        assert_eq!(
+            addresses,
+            [
+                (0x55d000000000, 0x55d000021000),
+                (0x55d000000000, 0x55d000042000),
+            ]
+        );
+        let statuses: Vec<DiffStatus> =
+            snapshot_diff.segments[1].iter().map(|d| d.status).collect();
+        assert_eq!(statuses, [DiffStatus::Removed, DiffStatus::Added]);
+
+        assert_eq!(snapshot_diff.groups[3].rss, 4 << 10);
+        assert_eq!(snapshot_diff.groups[4].rss, -(4 << 10));
+        assert_eq!(snapshot_diff.segments[2][0].rss, 0);
+    }
+
+    #[test]
+    fn diff_keeps_apart_groups_that_do_not_overlap() {
+        let baseline = capture("55d000021000", 16, "/usr/lib/libm.so.6");
+        let mut current = baseline.clone();
+        for mm in current[1].iter_mut() {
+            mm.address = (0x56d000000000, 0x56d000021000);
+        }
+        let (memory_maps, snapshot_diff) = diff(&baseline, &current);
+
+        assert_eq!(memory_maps.len(), baseline.len() + 1);
+        assert_eq!(snapshot_diff.groups[1].status, DiffStatus::Added);
+        assert_eq!(snapshot_diff.groups[4].status, DiffStatus::Removed);
+        assert_eq!(memory_maps[4][0].address.0, 0x55d000000000);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::app::{mmpath_to_string, vm_flag_names, AppResult, MemoryMapMatrix, UNSUMMABLE_FIELDS};
+use clap::ValueEnum;
+use procfs::process::MMapPath::Rollup;
+use procfs::process::MemoryMap;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use std::io::Write;
+
+/// Output formats of the `export` command.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// What a row of the csv export stands for.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ExportRows {
+    /// One row per mapping.
+    Mapping,
+    /// One row per path group, with its fields added up.
+    Path,
+}
+
+/// Write `memory_maps` to `out` without setting up the terminal. `rows` is
+/// only used by the csv format.
+pub fn export(
+    memory_maps: &MemoryMapMatrix,
+    format: ExportFormat,
+    rows: ExportRows,
+    out: impl Write,
+) -> AppResult<()> {
+    match format {
+        ExportFormat::Json => json(memory_maps, out),
+        ExportFormat::Csv => csv(memory_maps, rows, out),
+    }
+}
+
+/// The rollup, if any, goes into its own field and every other group is
+/// listed with its path and mappings in address order.
+fn json(memory_maps: &MemoryMapMatrix, mut out: impl Write) -> AppResult<()> {
+    let mut rollup = Value::Null;
+    let mut groups = Vec::new();
+    for group in memory_maps.iter() {
+        if group[0].pathname == Rollup {
+            rollup = json_mapping(&group[0]);
+            continue;
+        }
+        groups.push(json!({
+            "pathname": mmpath_to_string(&group[0].pathname),
+            "mappings": group.iter().map(json_mapping).collect::<Vec<Value>>(),
+        }));
+    }
+    serde_json::to_writer_pretty(&mut out, &json!({ "rollup": rollup, "groups": groups }))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The smaps field columns are every key found in the maps, in sorted order.
+/// The rollup is left out, it is the sum of all other rows.
+fn csv(memory_maps: &MemoryMapMatrix, rows: ExportRows, mut out: impl Write) -> AppResult<()> {
+    let groups: Vec<&Vec<MemoryMap>> = memory_maps
+        .iter()
+        .filter(|group| group[0].pathname != Rollup)
+        .collect();
+    let keys: BTreeSet<&String> = groups
+        .iter()
+        .flat_map(|group| group.iter())
+        .flat_map(|mm| mm.extension.map.keys())
+        .collect();
+
+    match rows {
+        ExportRows::Mapping => {
+            let header = [
+                "start", "end", "perms", "offset", "dev", "inode", "pathname", "vm_flags",
+            ];
+            write_csv_row(
+                &mut out,
+                header.into_iter().chain(keys.iter().map(|k| k.as_str())),
+            )?;
+            for mm in groups.iter().flat_map(|group| group.iter()) {
+                let mut row = vec![
+                    format!("{:#x}", mm.address.0),
+                    format!("{:#x}", mm.address.1),
+                    mm.perms.as_str(),
+                    mm.offset.to_string(),
+                    format!("{}:{}", mm.dev.0, mm.dev.1),
+                    mm.inode.to_string(),
+                    mmpath_to_string(&mm.pathname),
+                    vm_flag_names(mm).join(" "),
+                ];
+                row.extend(keys.iter().map(|k| {
+                    mm.extension
+                        .map
+                        .get(*k)
+                        .map(|v| v.to_string())
+                        .unwrap_or_default()
+                }));
+                write_csv_row(&mut out, row.iter().map(|v| v.as_str()))?;
+            }
+        }
+        ExportRows::Path => {
+            let keys: Vec<&String> = keys
+                .into_iter()
+                .filter(|k| !UNSUMMABLE_FIELDS.contains(&k.as_str()))
+                .collect();
+            let header = ["pathname", "start", "end", "mappings"];
+            write_csv_row(
+                &mut out,
+                header.into_iter().chain(keys.iter().map(|k| k.as_str())),
+            )?;
+            for group in groups.iter() {
+                let mut row = vec![
+                    mmpath_to_string(&group[0].pathname),
+                    format!("{:#x}", group[0].address.0),
+                    format!("{:#x}", group[group.len() - 1].address.1),
+                    group.len().to_string(),
+                ];
+                row.extend(keys.iter().map(|k| {
+                    group
+                        .iter()
+                        .map(|mm| *mm.extension.map.get(*k).unwrap_or(&0))
+                        .sum::<u64>()
+                        .to_string()
+                }));
+                write_csv_row(&mut out, row.iter().map(|v| v.as_str()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_csv_row<'a>(out: &mut impl Write, fields: impl Iterator<Item = &'a str>) -> AppResult<()> {
+    let fields: Vec<String> = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    writeln!(out, "{}", fields.join(","))?;
+    Ok(())
+}
+
+fn json_mapping(mm: &MemoryMap) -> Value {
+    // Addresses are written in hex like the ui does. The top of the address
+    // space doesn't fit a double, which is how most json readers see numbers.
+    let fields: Map<String, Value> = mm
+        .extension
+        .map
+        .iter()
+        .map(|(k, v)| (k.clone(), json!(v)))
+        .collect();
+    json!({
+        "address": {
+            "start": format!("{:#x}", mm.address.0),
+            "end": format!("{:#x}", mm.address.1),
+        },
+        "perms": mm.perms.as_str(),
+        "offset": mm.offset,
+        "dev": format!("{}:{}", mm.dev.0, mm.dev.1),
+        "inode": mm.inode,
+        "pathname": mmpath_to_string(&mm.pathname),
+        "vm_flags": vm_flag_names(mm),
+        "fields": fields,
+    })
+}
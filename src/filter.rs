@@ -0,0 +1,524 @@
+use crate::app::{mmpath_to_string, vm_flag_names, AppResult, Category, MemoryMapMatrix};
+use procfs::process::MemoryMap;
+
+/// Characters that turn the `/` filter from a fuzzy match into a query.
+const QUERY_OPERATORS: [char; 4] = ['<', '>', '=', '~'];
+
+/// Whether `text` is meant as a query rather than a fuzzy pattern.
+pub fn is_query(text: &str) -> bool {
+    text.contains(QUERY_OPERATORS)
+}
+
+/// A parsed filter expression such as `rss > 10M && perms ~ "x" && path ~ libc`.
+///
+/// Comparisons are combined with `&&`, `||`, `!` and parentheses. Numbers
+/// take decimal (`K`, `M`, `G`, `T`) or binary (`Ki`, `Mi`, ...) suffixes
+/// and `0x` for hex. Text is compared with `==`, `!=`, `~` (contains) and
+/// `!~`, ignoring case.
+///
+/// Units are decimal like the sizes on screen, so `kB` is 1000 bytes even
+/// though smaps uses it for 1024: write `KiB` for those.
+#[derive(Clone, Debug)]
+pub struct Query(Expr);
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Field, Op, Value),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Field {
+    Path,
+    Perms,
+    Flags,
+    Dev,
+    Kind,
+    Inode,
+    Offset,
+    Start,
+    End,
+    /// Any other name is looked up in the smaps fields.
+    Smaps(String),
+}
+
+impl Field {
+    fn from_name(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "path" => Field::Path,
+            "perms" => Field::Perms,
+            "flags" | "vm_flags" => Field::Flags,
+            "dev" => Field::Dev,
+            "kind" => Field::Kind,
+            "inode" => Field::Inode,
+            "offset" => Field::Offset,
+            "start" => Field::Start,
+            "end" => Field::End,
+            _ => Field::Smaps(name.to_string()),
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(
+            self,
+            Field::Path | Field::Perms | Field::Flags | Field::Dev | Field::Kind
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    NotContains,
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(u64),
+    Text(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> AppResult<Self> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("unexpected {}", describe(token)).into());
+        }
+        Ok(Query(expr))
+    }
+
+    /// The first smaps field used by the query that none of the mappings
+    /// in `memory_maps` has, most likely a typo.
+    pub fn unknown_field(&self, memory_maps: &MemoryMapMatrix) -> Option<String> {
+        let mut fields = Vec::new();
+        smaps_fields(&self.0, &mut fields);
+        fields.into_iter().find(|field| {
+            !memory_maps.iter().flatten().any(|mm| {
+                mm.extension
+                    .map
+                    .keys()
+                    .any(|k| k.eq_ignore_ascii_case(field))
+            })
+        })
+    }
+
+    pub fn matches(&self, mm: &MemoryMap) -> bool {
+        evaluate(&self.0, mm)
+    }
+}
+
+fn smaps_fields(expr: &Expr, fields: &mut Vec<String>) {
+    match expr {
+        Expr::And(a, b) | Expr::Or(a, b) => {
+            smaps_fields(a, fields);
+            smaps_fields(b, fields);
+        }
+        Expr::Not(a) => smaps_fields(a, fields),
+        Expr::Compare(Field::Smaps(name), _, _) => fields.push(name.clone()),
+        Expr::Compare(..) => {}
+    }
+}
+
+fn evaluate(expr: &Expr, mm: &MemoryMap) -> bool {
+    match expr {
+        Expr::And(a, b) => evaluate(a, mm) && evaluate(b, mm),
+        Expr::Or(a, b) => evaluate(a, mm) || evaluate(b, mm),
+        Expr::Not(a) => !evaluate(a, mm),
+        Expr::Compare(field, op, Value::Text(value)) => {
+            let text = match field {
+                Field::Path => mmpath_to_string(&mm.pathname),
+                Field::Perms => mm.perms.as_str(),
+                Field::Flags => vm_flag_names(mm).join(" "),
+                Field::Dev => format!("{}:{}", mm.dev.0, mm.dev.1),
+                Field::Kind => Category::of(&mm.pathname).name().to_string(),
+                _ => return false,
+            }
+            .to_lowercase();
+            let value = value.to_lowercase();
+            match op {
+                Op::Eq => text == value,
+                Op::Ne => text != value,
+                Op::Contains => text.contains(&value),
+                Op::NotContains => !text.contains(&value),
+                _ => false,
+            }
+        }
+        Expr::Compare(field, op, Value::Number(value)) => {
+            let number = match field {
+                Field::Inode => Some(mm.inode),
+                Field::Offset => Some(mm.offset),
+                Field::Start => Some(mm.address.0),
+                Field::End => Some(mm.address.1),
+                Field::Smaps(name) => mm
+                    .extension
+                    .map
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                    .map(|(_, v)| *v),
+                _ => None,
+            };
+            // A mapping without the field never matches, whatever the operator.
+            let Some(number) = number else {
+                return false;
+            };
+            match op {
+                Op::Eq => number == *value,
+                Op::Ne => number != *value,
+                Op::Lt => number < *value,
+                Op::Le => number <= *value,
+                Op::Gt => number > *value,
+                Op::Ge => number >= *value,
+                _ => false,
+            }
+        }
+    }
+}
+
+fn tokenize(text: &str) -> AppResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' | '|' => {
+                if chars.next_if_eq(&c).is_none() {
+                    return Err(format!("expected '{0}{0}'", c).into());
+                }
+                if c == '&' {
+                    Token::And
+                } else {
+                    Token::Or
+                }
+            }
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' if chars.next_if_eq(&'~').is_some() => Token::Op(Op::NotContains),
+            '!' => Token::Not,
+            '=' => {
+                chars.next_if_eq(&'=');
+                Token::Op(Op::Eq)
+            }
+            '~' => Token::Op(Op::Contains),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => quoted.extend(chars.next()),
+                        Some(c) => quoted.push(c),
+                        None => return Err("missing closing '\"'".into()),
+                    }
+                }
+                Token::Quoted(quoted)
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()&|!=~<>\"".contains(*c))
+                {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.pos) == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> AppResult<Expr> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> AppResult<Expr> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> AppResult<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err("missing closing ')'".into());
+                }
+                Ok(expr)
+            }
+            Some(Token::Word(name)) => self.compare(Field::from_name(&name), &name),
+            Some(token) => Err(format!("expected a field name, found {}", describe(&token)).into()),
+            None => Err("expected a field name".into()),
+        }
+    }
+
+    fn compare(&mut self, field: Field, name: &str) -> AppResult<Expr> {
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            _ => return Err(format!("expected an operator after '{}'", name).into()),
+        };
+        let value = match self.next() {
+            Some(Token::Word(v)) | Some(Token::Quoted(v)) => v,
+            _ => return Err(format!("expected a value after '{}'", name).into()),
+        };
+        let value = if field.is_text() {
+            if !matches!(op, Op::Eq | Op::Ne | Op::Contains | Op::NotContains) {
+                return Err(format!("'{}' is text, compare it with ==, !=, ~ or !~", name).into());
+            }
+            Value::Text(value)
+        } else {
+            if matches!(op, Op::Contains | Op::NotContains) {
+                return Err(format!("'{}' is a number, ~ only works on text", name).into());
+            }
+            Value::Number(parse_number(&value)?)
+        };
+        Ok(Expr::Compare(field, op, value))
+    }
+}
+
+/// `4096`, `0x1000`, `10M`, `1.5G` or `64KiB`. `K` and `kB` are 1000.
+fn parse_number(text: &str) -> AppResult<u64> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16)
+            .map_err(|_| format!("'{}' is not a number", text).into());
+    }
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, suffix) = text.split_at(split);
+    let suffix = suffix.to_lowercase();
+    let multiplier: u64 = match suffix.strip_suffix('b').unwrap_or(&suffix) {
+        "" => 1,
+        "k" => 1_000,
+        "m" => 1_000_000,
+        "g" => 1_000_000_000,
+        "t" => 1_000_000_000_000,
+        "ki" => 1 << 10,
+        "mi" => 1 << 20,
+        "gi" => 1 << 30,
+        "ti" => 1 << 40,
+        _ => return Err(format!("'{}' is not a number", text).into()),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", text))?;
+    Ok((number * multiplier as f64) as u64)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(v) => format!("'{}'", v),
+        Token::Quoted(v) => format!("\"{}\"", v),
+        Token::Op(_) => "an operator".to_string(),
+        Token::And => "'&&'".to_string(),
+        Token::Or => "'||'".to_string(),
+        Token::Not => "'!'".to_string(),
+        Token::Open => "'('".to_string(),
+        Token::Close => "')'".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use procfs::process::MemoryMaps;
+    use procfs::FromBufRead;
+
+    const SMAPS: &str = "\
+7f0000000000-7f0000002000 r-xp 00001000 fd:01 1234 /usr/lib/libc.so.6
+Size:                  8 kB
+Rss:                   8 kB
+Pss:                   4 kB
+VmFlags: rd ex mr mw me
+7ffd00000000-7ffd00021000 rw-p 00000000 00:00 0 [stack]
+Size:                132 kB
+Rss:                  16 kB
+Pss:                  16 kB
+VmFlags: rd wr mr mw me gd ac
+";
+
+    fn maps() -> Vec<MemoryMap> {
+        MemoryMaps::from_buf_read(SMAPS.as_bytes())
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    /// Which of the two mappings of [`SMAPS`] `query` matches.
+    fn matching(query: &str) -> Vec<bool> {
+        let query = Query::parse(query).unwrap();
+        maps().iter().map(|mm| query.matches(mm)).collect()
+    }
+
+    fn error(query: &str) -> String {
+        Query::parse(query).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn is_query_needs_an_operator() {
+        assert!(is_query("rss > 1M"));
+        assert!(is_query("path~libc"));
+        assert!(is_query("perms == r-xp"));
+        assert!(!is_query("libc"));
+        assert!(!is_query("(stack) && heap"));
+    }
+
+    #[test]
+    fn numbers_compare() {
+        assert_eq!(matching("rss == 8KiB"), [true, false]);
+        assert_eq!(matching("rss != 8KiB"), [false, true]);
+        assert_eq!(matching("rss < 16KiB"), [true, false]);
+        assert_eq!(matching("rss <= 16KiB"), [true, true]);
+        assert_eq!(matching("rss > 8KiB"), [false, true]);
+        assert_eq!(matching("rss >= 8KiB"), [true, true]);
+        assert_eq!(matching("Pss = 4096"), [true, false]);
+        assert_eq!(matching("offset == 0x1000"), [true, false]);
+        assert_eq!(matching("inode == 1234"), [true, false]);
+        assert_eq!(matching("start >= 0x7ffd00000000"), [false, true]);
+    }
+
+    #[test]
+    fn text_compares_ignoring_case() {
+        assert_eq!(matching("path ~ LIBC"), [true, false]);
+        assert_eq!(matching("path !~ libc"), [false, true]);
+        assert_eq!(matching("perms == r-xp"), [true, false]);
+        assert_eq!(matching("perms != r-xp"), [false, true]);
+        assert_eq!(matching("flags ~ gd"), [false, true]);
+        assert_eq!(matching("dev == 253:1"), [true, false]);
+        assert_eq!(matching("kind == stacks"), [false, true]);
+    }
+
+    #[test]
+    fn quoted_strings() {
+        assert_eq!(matching(r#"path ~ "libc.so""#), [true, false]);
+        assert_eq!(matching(r#"path ~ "c.so.6 ""#), [false, false]);
+        assert_eq!(matching(r#"path ~ "\"""#), [false, false]);
+        assert_eq!(matching(r#"path == "STACK""#), [false, true]);
+    }
+
+    #[test]
+    fn missing_fields_never_match() {
+        assert_eq!(matching("swap == 0"), [false, false]);
+        assert_eq!(matching("swap != 0"), [false, false]);
+        let query = Query::parse("swap > 0 || rss > 0").unwrap();
+        let maps: MemoryMapMatrix = vec![maps()];
+        assert_eq!(query.unknown_field(&maps), Some("swap".to_string()));
+        let query = Query::parse("RSS > 0").unwrap();
+        assert_eq!(query.unknown_field(&maps), None);
+    }
+
+    #[test]
+    fn precedence() {
+        // && binds tighter than ||, ! tighter than both.
+        assert_eq!(
+            matching("path ~ libc || rss > 8KiB && perms == r-xp"),
+            [true, false]
+        );
+        assert_eq!(
+            matching("(path ~ libc || rss > 8KiB) && perms == r-xp"),
+            [true, false]
+        );
+        assert_eq!(
+            matching("(path ~ libc || rss > 8KiB) && perms != r-xp"),
+            [false, true]
+        );
+        assert_eq!(matching("!path ~ libc && rss > 0"), [false, true]);
+        assert_eq!(matching("!(path ~ libc || rss > 8KiB)"), [false, false]);
+        assert_eq!(matching("!!path ~ libc"), [true, false]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(error("rss > 1M & pss > 0"), "expected '&&'");
+        assert_eq!(error("rss > 1M | pss > 0"), "expected '||'");
+        assert_eq!(error(r#"path ~ "libc"#), "missing closing '\"'");
+        assert_eq!(error("(rss > 1M"), "missing closing ')'");
+        assert_eq!(error("rss > 1M)"), "unexpected ')'");
+        assert_eq!(error("rss > 1M pss"), "unexpected 'pss'");
+        assert_eq!(error(""), "expected a field name");
+        assert_eq!(error("rss > 1M &&"), "expected a field name");
+        assert_eq!(error("> 1M"), "expected a field name, found an operator");
+        assert_eq!(error("rss"), "expected an operator after 'rss'");
+        assert_eq!(error("rss >"), "expected a value after 'rss'");
+        assert_eq!(
+            error("path > 1"),
+            "'path' is text, compare it with ==, !=, ~ or !~"
+        );
+        assert_eq!(error("rss ~ 1"), "'rss' is a number, ~ only works on text");
+        assert_eq!(error("rss > 1X"), "'1X' is not a number");
+        assert_eq!(error("rss > 0xg"), "'0xg' is not a number");
+        assert_eq!(error("rss > M"), "'M' is not a number");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("4096").unwrap(), 4096);
+        assert_eq!(parse_number("0x1000").unwrap(), 4096);
+        assert_eq!(parse_number("0X1F").unwrap(), 31);
+        assert_eq!(parse_number("10M").unwrap(), 10_000_000);
+        assert_eq!(parse_number("1.5G").unwrap(), 1_500_000_000);
+        assert_eq!(parse_number("2T").unwrap(), 2_000_000_000_000);
+        assert_eq!(parse_number("8k").unwrap(), 8_000);
+        assert_eq!(parse_number("8kB").unwrap(), 8_000);
+        assert_eq!(parse_number("64KiB").unwrap(), 64 << 10);
+        assert_eq!(parse_number("3mi").unwrap(), 3 << 20);
+        assert_eq!(parse_number("1GiB").unwrap(), 1 << 30);
+        assert_eq!(parse_number("1Ti").unwrap(), 1 << 40);
+        assert!(parse_number("8kbb").is_err());
+        assert!(parse_number("1.2.3").is_err());
+        assert!(parse_number("").is_err());
+    }
+}
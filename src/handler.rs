@@ -1,8 +1,24 @@
 use crate::app::{App, AppResult, AppSelectedPane};
+use crate::processes::ProcessMatch;
+use crate::session::Session;
+use crate::timeline::Timeline;
+use crate::ui::{AddressSpaceWidget, ProcessPickerWidget, TreeWidget, TreemapWidget};
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 /// Handles the key events and updates the state of [`App`].
 pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    if app.command_widget.toggle {
+        match key_event.code {
+            KeyCode::Esc => app.command_widget.toggle(),
+            KeyCode::Enter => app.run_command(),
+            KeyCode::Backspace => {
+                app.command_widget.input.pop();
+            }
+            KeyCode::Char(value) => app.command_widget.input.push(value),
+            _ => (),
+        }
+        return Ok(());
+    }
     match app.path_list_widget.toggle {
         true => match key_event.code {
             KeyCode::Char('/') | KeyCode::Enter => app.path_list_widget.toggle(),
@@ -16,38 +32,191 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             KeyCode::Esc | KeyCode::Char('q') => {
                 app.quit();
             }
-            KeyCode::Char('c') | KeyCode::Char('C') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    app.quit();
-                }
+            KeyCode::Char('c') | KeyCode::Char('C')
+                if key_event.modifiers == KeyModifiers::CONTROL =>
+            {
+                app.quit();
             }
             KeyCode::Char('j') | KeyCode::Down => match app.selected_pane {
                 AppSelectedPane::Path => app.path_list_widget.next(),
                 AppSelectedPane::Segment => app.segment_list_widget.next(),
+                AppSelectedPane::Tree => app.move_tree(TreeWidget::next),
+                AppSelectedPane::Treemap => app.move_treemap(TreemapWidget::next),
+                AppSelectedPane::AddressSpace => app.move_address_space(AddressSpaceWidget::next),
             },
 
             KeyCode::Char('k') | KeyCode::Up => match app.selected_pane {
                 AppSelectedPane::Path => app.path_list_widget.previous(),
                 AppSelectedPane::Segment => app.segment_list_widget.previous(),
+                AppSelectedPane::Tree => app.move_tree(TreeWidget::previous),
+                AppSelectedPane::Treemap => app.move_treemap(TreemapWidget::previous),
+                AppSelectedPane::AddressSpace => {
+                    app.move_address_space(AddressSpaceWidget::previous)
+                }
             },
             KeyCode::Char('g') => match app.selected_pane {
                 AppSelectedPane::Path => app.path_list_widget.go_top(),
                 AppSelectedPane::Segment => app.segment_list_widget.go_top(),
+                AppSelectedPane::Tree => app.move_tree(TreeWidget::go_top),
+                AppSelectedPane::Treemap => app.move_treemap(TreemapWidget::go_top),
+                AppSelectedPane::AddressSpace => app.move_address_space(AddressSpaceWidget::go_top),
             },
             KeyCode::Char('G') => match app.selected_pane {
                 AppSelectedPane::Path => app.path_list_widget.go_bottom(),
                 AppSelectedPane::Segment => app.segment_list_widget.go_bottom(),
+                AppSelectedPane::Tree => app.move_tree(TreeWidget::go_bottom),
+                AppSelectedPane::Treemap => app.move_treemap(TreemapWidget::go_bottom),
+                AppSelectedPane::AddressSpace => {
+                    app.move_address_space(AddressSpaceWidget::go_bottom)
+                }
             },
             KeyCode::Tab => app.switch_pane(),
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                if let AppSelectedPane::Tree = app.selected_pane {
+                    app.move_tree(TreeWidget::toggle_selected)
+                }
+            }
+            KeyCode::Left => {
+                if let AppSelectedPane::Tree = app.selected_pane {
+                    app.move_tree(TreeWidget::close)
+                }
+            }
+            KeyCode::Right => {
+                if let AppSelectedPane::Tree = app.selected_pane {
+                    app.move_tree(TreeWidget::open)
+                }
+            }
             KeyCode::Char('/') => app.path_list_widget.toggle(),
+            KeyCode::Char(':') => app.command_widget.toggle(),
             KeyCode::Char('h') => {
                 app.help_widget.toggle();
                 app.legend_widget.help_toggled();
             }
             KeyCode::Char('v') => app.help_widget.toggle_vm_flags(),
+            KeyCode::Char('r') => app.refresh(),
+            KeyCode::Char('b') => app.mark_baseline(),
+            KeyCode::Char('s') => app.cycle_sort(),
+            KeyCode::Char('m') => app.toggle_grouping(),
+            KeyCode::Char('t') => app.toggle_tree(),
+            KeyCode::Char('a') => app.toggle_address_space(),
+            KeyCode::Char('T') => app.toggle_treemap(),
+            KeyCode::Char('R') => app.toggle_pagemap(),
+            KeyCode::Char('[') => app.seek(Timeline::previous),
+            KeyCode::Char(']') => app.seek(Timeline::next),
+            KeyCode::Char('{') => app.seek(Timeline::first),
+            KeyCode::Char('}') => app.seek(Timeline::last),
             _ => {}
         },
     }
 
     Ok(())
 }
+
+/// Handles the key events of the [`Session`]: the picker, the comparison,
+/// the list of who maps a file or the threads while open, the tab keys,
+/// and everything else in the tab on screen.
+pub fn handle_session_key_events(key_event: KeyEvent, session: &mut Session) -> AppResult<()> {
+    if let Some(picker) = session.picker.as_mut() {
+        handle_picker_key_events(key_event, picker)?;
+        session.update_picker();
+        return Ok(());
+    }
+    if let Some(compare) = session.compare.as_mut() {
+        match key_event.code {
+            KeyCode::Char('q') => session.quit(),
+            KeyCode::Char('c') | KeyCode::Char('C')
+                if key_event.modifiers == KeyModifiers::CONTROL =>
+            {
+                session.quit()
+            }
+            KeyCode::Esc | KeyCode::Char('c') => session.toggle_compare(),
+            KeyCode::Char('j') | KeyCode::Down => compare.next(),
+            KeyCode::Char('k') | KeyCode::Up => compare.previous(),
+            KeyCode::Char('g') => compare.go_top(),
+            KeyCode::Char('G') => compare.go_bottom(),
+            KeyCode::Tab => compare.switch_pane(),
+            _ => {}
+        }
+        return Ok(());
+    }
+    if let Some(threads) = session.threads.as_mut() {
+        match key_event.code {
+            KeyCode::Char('q') => session.quit(),
+            KeyCode::Char('c') | KeyCode::Char('C')
+                if key_event.modifiers == KeyModifiers::CONTROL =>
+            {
+                session.quit()
+            }
+            KeyCode::Esc | KeyCode::Char('H') => session.toggle_threads(),
+            KeyCode::Char('j') | KeyCode::Down => threads.next(),
+            KeyCode::Char('k') | KeyCode::Up => threads.previous(),
+            KeyCode::Char('g') => threads.go_top(),
+            KeyCode::Char('G') => threads.go_bottom(),
+            KeyCode::Enter => session.open_threads_selected(),
+            _ => {}
+        }
+        return Ok(());
+    }
+    if let Some(who_maps) = session.who_maps.as_mut() {
+        match key_event.code {
+            KeyCode::Char('q') => session.quit(),
+            KeyCode::Char('c') | KeyCode::Char('C')
+                if key_event.modifiers == KeyModifiers::CONTROL =>
+            {
+                session.quit()
+            }
+            KeyCode::Esc | KeyCode::Char('w') => session.toggle_who_maps(),
+            KeyCode::Char('j') | KeyCode::Down => who_maps.next(),
+            KeyCode::Char('k') | KeyCode::Up => who_maps.previous(),
+            KeyCode::Char('g') => who_maps.go_top(),
+            KeyCode::Char('G') => who_maps.go_bottom(),
+            KeyCode::Char('r') => who_maps.reload(),
+            KeyCode::Enter => session.open_who_maps_selected(),
+            _ => {}
+        }
+        return Ok(());
+    }
+    let Some(app) = session.app() else {
+        return Ok(());
+    };
+    // Keys typed into the filter or the prompt belong to the tab.
+    if app.path_list_widget.toggle || app.command_widget.toggle {
+        return handle_key_events(key_event, app);
+    }
+    match key_event.code {
+        KeyCode::Char(value @ '1'..='9') => session.select(value as usize - '1' as usize),
+        KeyCode::Char('p') => session.open_picker(ProcessMatch::default()),
+        KeyCode::Char('x') => session.close(),
+        KeyCode::Char('c') if key_event.modifiers == KeyModifiers::NONE => session.toggle_compare(),
+        KeyCode::Char('w') => session.toggle_who_maps(),
+        KeyCode::Char('H') => session.toggle_threads(),
+        _ => handle_key_events(key_event, app)?,
+    }
+    Ok(())
+}
+
+/// Handles the key events of the process picker. Typing goes to the filter.
+fn handle_picker_key_events(
+    key_event: KeyEvent,
+    picker: &mut ProcessPickerWidget,
+) -> AppResult<()> {
+    match key_event.code {
+        KeyCode::Esc => picker.quit(),
+        KeyCode::Char('c') | KeyCode::Char('C') if key_event.modifiers == KeyModifiers::CONTROL => {
+            picker.quit()
+        }
+        KeyCode::Char('r') if key_event.modifiers == KeyModifiers::CONTROL => picker.reload(),
+        KeyCode::Down => picker.next(),
+        KeyCode::Up => picker.previous(),
+        KeyCode::Home => picker.go_top(),
+        KeyCode::End => picker.go_bottom(),
+        KeyCode::Tab => picker.cycle_order(),
+        KeyCode::Enter => picker.pick(),
+        KeyCode::Backspace => {
+            picker.filter.pop();
+        }
+        KeyCode::Char(value) => picker.filter.push(value),
+        _ => {}
+    }
+    Ok(())
+}
@@ -1,5 +1,19 @@
 pub mod app;
+pub mod cgroup;
+pub mod compare;
+pub mod diff;
 pub mod event;
+pub mod export;
+pub mod filter;
 pub mod handler;
+pub mod lookup;
+pub mod pagemap;
+pub mod processes;
+pub mod report;
+pub mod session;
+pub mod stale;
+pub mod threads;
+pub mod timeline;
 pub mod tui;
 pub mod ui;
+pub mod whomaps;
@@ -0,0 +1,185 @@
+use crate::app::{mmpath_to_string, AppResult, MemoryMapMatrix};
+use procfs::process::MMapPath::Rollup;
+use procfs::process::MemoryMap;
+use std::io::Write;
+use std::num::ParseIntError;
+
+/// Where an address falls, as group and segment indexes into a
+/// [`MemoryMapMatrix`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    Mapped(usize, usize),
+    /// Not mapped, with the closest mappings below and above if any.
+    Hole {
+        below: Option<(usize, usize)>,
+        above: Option<(usize, usize)>,
+    },
+}
+
+/// Addresses are always hex, with or without the `0x` prefix, as they come
+/// out of crash dumps and perf.
+pub fn parse_address(text: &str) -> Result<u64, ParseIntError> {
+    let text = text.trim();
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u64::from_str_radix(hex, 16)
+}
+
+/// Find the mapping holding `address`. The rollup spans the whole address
+/// space, so it is left out.
+pub fn lookup(memory_maps: &MemoryMapMatrix, address: u64) -> Lookup {
+    lookup_where(memory_maps, address, |_| true)
+}
+
+/// Like [`lookup`], among the mappings `keep` takes by group and segment
+/// index only.
+pub fn lookup_where(
+    memory_maps: &MemoryMapMatrix,
+    address: u64,
+    keep: impl Fn((usize, usize)) -> bool,
+) -> Lookup {
+    let mut below: Option<(usize, usize)> = None;
+    let mut above: Option<(usize, usize)> = None;
+    for (group_idx, group) in memory_maps.iter().enumerate() {
+        for (segment_idx, mm) in group.iter().enumerate() {
+            if mm.pathname == Rollup || !keep((group_idx, segment_idx)) {
+                continue;
+            }
+            if mm.address.0 <= address && address < mm.address.1 {
+                return Lookup::Mapped(group_idx, segment_idx);
+            }
+            let at = |(g, s): (usize, usize)| &memory_maps[g][s];
+            if mm.address.1 <= address && below.is_none_or(|v| at(v).address.1 < mm.address.1) {
+                below = Some((group_idx, segment_idx));
+            }
+            if mm.address.0 > address && above.is_none_or(|v| at(v).address.0 > mm.address.0) {
+                above = Some((group_idx, segment_idx));
+            }
+        }
+    }
+    Lookup::Hole { below, above }
+}
+
+/// Print the mapping holding each of `addresses`, or its neighbours when it
+/// falls in a hole.
+pub fn addr2map(
+    memory_maps: &MemoryMapMatrix,
+    addresses: &[u64],
+    mut out: impl Write,
+) -> AppResult<()> {
+    for address in addresses {
+        match lookup(memory_maps, *address) {
+            Lookup::Mapped(g, s) => {
+                let mm = &memory_maps[g][s];
+                // The file offset is what symbolizers want for file-backed code.
+                writeln!(
+                    out,
+                    "{:#x}  {}  +{:#x} (file offset {:#x})",
+                    address,
+                    describe(mm),
+                    address - mm.address.0,
+                    mm.offset + (address - mm.address.0)
+                )?;
+            }
+            Lookup::Hole { below, above } => {
+                writeln!(out, "{:#x}  not mapped", address)?;
+                if let Some((g, s)) = below {
+                    let mm = &memory_maps[g][s];
+                    let distance = address - mm.address.1 + 1;
+                    writeln!(out, "  below  {}  ({:#x} before)", describe(mm), distance)?;
+                }
+                if let Some((g, s)) = above {
+                    let mm = &memory_maps[g][s];
+                    let distance = mm.address.0 - address;
+                    writeln!(out, "  above  {}  ({:#x} after)", describe(mm), distance)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A mapping on one line: `start-end perms path`.
+pub fn describe(mm: &MemoryMap) -> String {
+    format!(
+        "{:#x}-{:#x} {} {}",
+        mm.address.0,
+        mm.address.1,
+        mm.perms.as_str(),
+        mmpath_to_string(&mm.pathname)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::SmapsSource;
+
+    /// Two libc mappings with a one page gap between them.
+    fn memory_maps() -> MemoryMapMatrix {
+        let smaps = "\
+7f0000000000-7f0000004000 ---p 00000000 00:00 0 [rollup]
+Rss: 12 kB
+7f0000000000-7f0000002000 r-xp 00000000 fd:01 1234 /usr/lib/libc.so.6
+Rss: 8 kB
+7f0000003000-7f0000004000 rw-p 00002000 fd:01 1234 /usr/lib/libc.so.6
+Rss: 4 kB
+";
+        SmapsSource::Text(smaps.to_string()).load().unwrap()
+    }
+
+    #[test]
+    fn parse_address_takes_hex_with_or_without_prefix() {
+        assert_eq!(parse_address("0x7f0000001000"), Ok(0x7f0000001000));
+        assert_eq!(parse_address("0X7F0000001000"), Ok(0x7f0000001000));
+        assert_eq!(parse_address("7f0000001000"), Ok(0x7f0000001000));
+        assert_eq!(parse_address(" 10\n"), Ok(0x10));
+        assert!(parse_address("0x").is_err());
+        assert!(parse_address("0xg").is_err());
+        assert!(parse_address("").is_err());
+    }
+
+    #[test]
+    fn lookup_boundaries() {
+        let memory_maps = memory_maps();
+        assert_eq!(lookup(&memory_maps, 0x7f0000000000), Lookup::Mapped(1, 0));
+        assert_eq!(lookup(&memory_maps, 0x7f0000001fff), Lookup::Mapped(1, 0));
+        assert_eq!(lookup(&memory_maps, 0x7f0000003000), Lookup::Mapped(1, 1));
+        assert_eq!(lookup(&memory_maps, 0x7f0000003fff), Lookup::Mapped(1, 1));
+    }
+
+    #[test]
+    fn lookup_gaps() {
+        let memory_maps = memory_maps();
+        let between = Lookup::Hole {
+            below: Some((1, 0)),
+            above: Some((1, 1)),
+        };
+        // The end of a mapping is the first address past it.
+        assert_eq!(lookup(&memory_maps, 0x7f0000002000), between);
+        assert_eq!(lookup(&memory_maps, 0x7f0000002fff), between);
+        assert_eq!(
+            lookup(&memory_maps, 0x7effffffffff),
+            Lookup::Hole {
+                below: None,
+                above: Some((1, 0)),
+            }
+        );
+        assert_eq!(
+            lookup(&memory_maps, 0x7f0000004000),
+            Lookup::Hole {
+                below: Some((1, 1)),
+                above: None,
+            }
+        );
+        assert_eq!(
+            lookup_where(&memory_maps, 0x7f0000001000, |idx| idx != (1, 0)),
+            Lookup::Hole {
+                below: None,
+                above: Some((1, 1)),
+            }
+        );
+    }
+}
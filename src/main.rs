@@ -1,23 +1,251 @@
-use clap::Parser;
+use clap::{Args as ClapArgs, Parser, Subcommand};
 use clap_stdin::MaybeStdin;
 use log::*;
-use smaps_explorer::app::App;
+use smaps_explorer::app::{App, AppResult, Grouping, MemoryMapMatrix, Metric, SmapsSource};
+use smaps_explorer::cgroup;
 use smaps_explorer::event::Event;
 use smaps_explorer::event::EventHandler;
-use smaps_explorer::handler::handle_key_events;
+use smaps_explorer::export::{self, ExportFormat, ExportRows};
+use smaps_explorer::handler::handle_session_key_events;
+use smaps_explorer::lookup;
+use smaps_explorer::processes::{self, ProcessMatch, ProcessSummary};
+use smaps_explorer::report;
+use smaps_explorer::session::Session;
+use smaps_explorer::stale;
+use smaps_explorer::threads;
+use smaps_explorer::timeline;
 use smaps_explorer::tui::Tui;
+use smaps_explorer::whomaps::{self, FileTarget};
 use ratatui::prelude::CrosstermBackend;
 use ratatui::Terminal;
 use std::error::Error;
 use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
 use tui_logger::*;
 
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(help = "or '-' for stdin.")]
-    pid: MaybeStdin<i32>,
-    #[arg(short, long, default_value_t = false)]
+    #[command(flatten)]
+    input: Input,
+    #[arg(
+        short,
+        long,
+        help = "compare the first tab against a saved smaps file."
+    )]
+    baseline: Option<PathBuf>,
+    #[arg(short, long, help = "re-read smaps every N seconds.")]
+    interval: Option<u64>,
+    #[arg(
+        short,
+        long,
+        value_name = "PID",
+        help = "open another process in a tab, can be repeated."
+    )]
+    tab: Vec<i32>,
+    #[arg(short, long, default_value_t = false, global = true)]
     debug: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Append periodic smaps snapshots of a process to a file.
+    Record {
+        #[arg(help = "or '-' for stdin.")]
+        pid: MaybeStdin<i32>,
+        #[arg(short, long, help = "file to append the snapshots to.")]
+        output: PathBuf,
+        #[arg(short, long, default_value_t = 10, help = "seconds between snapshots.")]
+        interval: u64,
+        #[arg(short, long, help = "stop after N snapshots.")]
+        count: Option<usize>,
+    },
+    /// Browse the snapshots of a recording.
+    Replay {
+        #[arg(help = "file written by record.")]
+        file: PathBuf,
+    },
+    /// Write the parsed memory maps to stdout.
+    Export {
+        #[command(flatten)]
+        input: Input,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = ExportRows::Mapping,
+            help = "what a csv row stands for."
+        )]
+        rows: ExportRows,
+    },
+    /// Print the totals and the largest path groups to stdout.
+    Report {
+        #[command(flatten)]
+        input: Input,
+        #[arg(short, long, value_enum, default_value_t = Metric::Rss, help = "metric to rank by.")]
+        sort: Metric,
+        #[arg(
+            short = 'n',
+            long,
+            default_value_t = 10,
+            help = "number of paths to show."
+        )]
+        top: usize,
+    },
+    /// Compare the path groups of two processes side by side.
+    Compare {
+        #[arg(help = "shown as A.")]
+        first: i32,
+        #[arg(help = "shown as B.")]
+        second: i32,
+    },
+    /// Print the mapping holding each address.
+    Addr2map {
+        #[command(flatten)]
+        input: Input,
+        #[arg(
+            short,
+            long = "address",
+            required = true,
+            num_args = 1..,
+            value_parser = lookup::parse_address,
+            help = "hex address, with or without 0x."
+        )]
+        addresses: Vec<u64>,
+    },
+    /// List the processes still mapping deleted or replaced files, e.g. old
+    /// libraries after an upgrade.
+    NeedsRestart,
+    /// List every process mapping a file, with the Rss and Pss of its mappings.
+    WhoMaps {
+        #[arg(
+            value_name = "FILE|DEV:INODE",
+            help = "path, or device and inode as in smaps, e.g. fd:01:1835023."
+        )]
+        file: FileTarget,
+    },
+}
+
+// The smaps to read, shared by the tui and the headless commands. Not a doc
+// comment, clap would use it as the about text of every command it is in.
+#[derive(ClapArgs, Debug)]
+struct Input {
+    #[arg(help = "or '-' for stdin (a pid or raw smaps text). Lists the processes when omitted.")]
+    pid: Option<MaybeStdin<SmapsSource>>,
+    #[arg(short, long, conflicts_with = "pid", help = "load a saved smaps file.")]
+    file: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with_all = ["pid", "file"],
+        help = "attach to the process with this comm or executable name."
+    )]
+    name: Option<String>,
+    #[arg(
+        long = "match",
+        conflicts_with_all = ["pid", "file"],
+        help = "attach to the process whose cmdline contains this."
+    )]
+    pattern: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["pid", "file", "name", "pattern"],
+        help = "read the smaps of a single thread, from task/<TID>/smaps of its process."
+    )]
+    tid: Option<i32>,
+    #[arg(
+        long,
+        conflicts_with_all = ["pid", "file", "name", "pattern", "tid"],
+        help = "add up every process of a cgroup, by path or relative to /sys/fs/cgroup."
+    )]
+    cgroup: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with_all = ["pid", "file", "name", "pattern", "tid", "cgroup"],
+        help = "add up every process of a systemd unit."
+    )]
+    unit: Option<String>,
+    #[arg(long, value_enum, default_value_t = Grouping::Contiguous, help = "how mappings are put into path groups.")]
+    group_by: Grouping,
+}
+
+/// What the [`Input`] selects.
+enum Selection {
+    Source(SmapsSource),
+    /// The processes matching `--name`/`--match` when several do, or none
+    /// when no process was given at all.
+    Pick(Vec<ProcessSummary>),
+}
+
+impl Input {
+    fn process_match(&self) -> ProcessMatch {
+        ProcessMatch {
+            name: self.name.clone(),
+            pattern: self.pattern.clone(),
+        }
+    }
+
+    /// The source to read, or else the process is left to pick.
+    fn source(&self) -> AppResult<Selection> {
+        match (&self.pid, &self.file) {
+            (_, Some(path)) => return Ok(Selection::Source(SmapsSource::File(path.clone()))),
+            (Some(source), None) => return Ok(Selection::Source((**source).clone())),
+            (None, None) => {}
+        }
+        if let Some(tid) = self.tid {
+            let source = SmapsSource::Thread(threads::thread_group(tid)?, tid);
+            return Ok(Selection::Source(source));
+        }
+        if let Some(cgroup) = &self.cgroup {
+            return Ok(Selection::Source(SmapsSource::Cgroup(cgroup::resolve(
+                cgroup,
+            )?)));
+        }
+        if let Some(unit) = &self.unit {
+            return Ok(Selection::Source(SmapsSource::Cgroup(cgroup::find_unit(
+                unit,
+            )?)));
+        }
+        let matching = self.process_match();
+        if matching.is_empty() {
+            return Ok(Selection::Pick(Vec::new()));
+        }
+        let mut found = processes::processes(&matching);
+        match found.len() {
+            0 => Err(format!("no process matches {}", matching).into()),
+            1 => Ok(Selection::Source(SmapsSource::Pid(found.remove(0).pid))),
+            _ => Ok(Selection::Pick(found)),
+        }
+    }
+
+    fn load(&self) -> AppResult<MemoryMapMatrix> {
+        let source = match self.source()? {
+            Selection::Source(source) => source,
+            Selection::Pick(found) if found.is_empty() => {
+                return Err(
+                    "a pid, --file, --name, --match, --tid, --cgroup or --unit is required".into(),
+                )
+            }
+            // There is no chooser outside the tui.
+            Selection::Pick(found) => {
+                let found: Vec<String> = found
+                    .iter()
+                    .map(|process| format!("{} ({})", process.pid, process.comm))
+                    .collect();
+                return Err(format!("several processes match: {}", found.join(", ")).into());
+            }
+        };
+        // Sources are read in contiguous groups, or per path for a cgroup,
+        // which has no address order to regroup by.
+        Ok(match self.group_by {
+            Grouping::Contiguous => source.load()?,
+            grouping => grouping.regroup(&source.load()?),
+        })
+    }
 }
 
 // https://github.com/ratatui-org/templates/blob/main/simple/src/main.rs
@@ -27,7 +255,74 @@ fn main() -> Result<(), Box<dyn Error>> {
     debug!(target:"App", "Logging initialized");
 
     let args = Args::parse();
-    let mut app = App::new(*args.pid, args.debug)?;
+    let app = match &args.command {
+        Some(Command::Record {
+            pid,
+            output,
+            interval,
+            count,
+        }) => {
+            return timeline::record(**pid, output, Duration::from_secs(*interval), *count);
+        }
+        Some(Command::Replay { file }) => {
+            Some(App::new(SmapsSource::Recording(file.clone()), args.debug)?)
+        }
+        // Both processes are opened in tabs below.
+        Some(Command::Compare { .. }) => None,
+        Some(Command::Export {
+            input,
+            format,
+            rows,
+        }) => {
+            let memory_maps = input.load()?;
+            return export::export(&memory_maps, *format, *rows, io::stdout().lock());
+        }
+        Some(Command::Report { input, sort, top }) => {
+            let memory_maps = input.load()?;
+            return report::report(&memory_maps, *sort, *top, io::stdout().lock());
+        }
+        Some(Command::Addr2map { input, addresses }) => {
+            let memory_maps = input.load()?;
+            return lookup::addr2map(&memory_maps, addresses, io::stdout().lock());
+        }
+        Some(Command::NeedsRestart) => {
+            return stale::report(io::stdout().lock());
+        }
+        Some(Command::WhoMaps { file }) => {
+            return whomaps::report(file, io::stdout().lock());
+        }
+        None => match args.input.source()? {
+            Selection::Source(source) => Some(App::new(source, args.debug)?),
+            // Without a pid the process is picked once the tui is up.
+            Selection::Pick(_) => None,
+        },
+    };
+    let mut session = Session::new(
+        args.debug,
+        args.interval.map(Duration::from_secs),
+        args.input.group_by,
+    );
+    if let Some(mut app) = app {
+        if let Some(path) = &args.baseline {
+            app.set_baseline(Rc::new(SmapsSource::File(path.clone()).load()?));
+        }
+        session.push(app);
+    }
+    for pid in &args.tab {
+        session.open(SmapsSource::Pid(*pid))?;
+    }
+    if let Some(Command::Compare { first, second }) = &args.command {
+        session.open(SmapsSource::Pid(*first))?;
+        session.open(SmapsSource::Pid(*second))?;
+    }
+    if session.apps.is_empty() {
+        session.open_picker(args.input.process_match());
+    } else {
+        session.select(0);
+    }
+    if let Some(Command::Compare { .. }) = &args.command {
+        session.toggle_compare();
+    }
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
@@ -37,13 +332,13 @@ fn main() -> Result<(), Box<dyn Error>> {
     tui.init()?;
 
     // Start the main loop.
-    while app.running() {
+    while session.running() {
         // Render the user interface.
-        tui.draw(&mut app)?;
+        tui.draw(&mut session)?;
         // Handle events.
         match tui.events.next()? {
-            Event::Tick => app.tick(),
-            Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
+            Event::Tick => session.tick(),
+            Event::Key(key_event) => handle_session_key_events(key_event, &mut session)?,
             Event::Mouse(_) => {}
             Event::Resize(_, _) => {}
         }
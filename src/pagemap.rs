@@ -0,0 +1,189 @@
+use crate::app::AppResult;
+use procfs::process::{MemoryPageFlags, PageInfo, Process, SwapPageFlags};
+use procfs::PhysicalPageFlags;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::FileExt;
+
+/// Pages read at most for one segment, 4 GiB of 4 KiB pages. Reservations
+/// of several GiB are common, e.g. for JIT heaps, and are cut there.
+const MAX_PAGES: usize = 1 << 20;
+
+/// Runs of consecutive frames read at most for one segment. Each takes two
+/// reads, frames scattered all over memory would otherwise take two per page
+/// while the segment is on screen.
+const MAX_FRAME_RUNS: usize = 4096;
+
+/// A page of a mapping as the page table has it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Page {
+    pub present: bool,
+    pub swapped: bool,
+    /// A page of the file, or anonymous memory shared with another process.
+    pub file_or_shared: bool,
+    /// Written since the soft-dirty bits were last cleared through
+    /// `/proc/<pid>/clear_refs`, or since the page was mapped.
+    pub soft_dirty: bool,
+    /// The page frame of a present page. Reads as 0 without CAP_SYS_ADMIN.
+    pub pfn: u64,
+}
+
+impl Page {
+    fn of(info: PageInfo) -> Self {
+        match info {
+            PageInfo::MemoryPage(flags) => Self {
+                present: flags.contains(MemoryPageFlags::PRESENT),
+                swapped: false,
+                file_or_shared: flags.contains(MemoryPageFlags::FILE),
+                soft_dirty: flags.contains(MemoryPageFlags::SOFT_DIRTY),
+                pfn: flags.get_page_frame_number().0,
+            },
+            PageInfo::SwapPage(flags) => Self {
+                present: false,
+                swapped: true,
+                file_or_shared: flags.contains(SwapPageFlags::FILE),
+                soft_dirty: flags.contains(SwapPageFlags::SOFT_DIRTY),
+                pfn: 0,
+            },
+        }
+    }
+}
+
+/// The pages of one mapping, read from `/proc/<pid>/pagemap`.
+#[derive(Clone, Debug)]
+pub struct Residency {
+    pub start: u64,
+    pub end: u64,
+    pub page_size: u64,
+    /// One entry per page from `start`, up to [`MAX_PAGES`].
+    pub pages: Vec<Page>,
+}
+
+impl Residency {
+    pub fn read(pid: i32, start: u64, end: u64) -> AppResult<Self> {
+        let page_size = procfs::page_size();
+        let first = (start / page_size) as usize;
+        let count = (((end - start) / page_size) as usize).min(MAX_PAGES);
+        let pages = Process::new(pid)?
+            .pagemap()?
+            .get_range_info(first..first + count)?
+            .into_iter()
+            .map(Page::of)
+            .collect();
+        Ok(Self {
+            start,
+            end,
+            page_size,
+            pages,
+        })
+    }
+
+    /// Whether the mapping has more pages than were read.
+    pub fn truncated(&self) -> bool {
+        (self.pages.len() as u64) < (self.end - self.start) / self.page_size
+    }
+
+    pub fn count(&self, bit: fn(&Page) -> bool) -> usize {
+        self.pages.iter().filter(|page| bit(page)).count()
+    }
+
+    /// The kernel hides page frames from users without CAP_SYS_ADMIN, all
+    /// present pages then read as frame 0.
+    pub fn pfns_hidden(&self) -> bool {
+        let mut present = self.pages.iter().filter(|page| page.present).peekable();
+        present.peek().is_some() && present.all(|page| page.pfn == 0)
+    }
+}
+
+/// How the page frames of a mapping are shared, from `/proc/kpagecount`
+/// and `/proc/kpageflags`. Both take root to read, and hold a u64 per frame.
+#[derive(Clone, Debug, Default)]
+pub struct Sharing {
+    /// Present pages by how many times their frame is mapped, by any
+    /// process.
+    pub map_counts: BTreeMap<u64, usize>,
+    pub thp: usize,
+    pub ksm: usize,
+    pub dirty: usize,
+    /// On the active LRU list, i.e. recently used.
+    pub active: usize,
+    /// Only the frames of the first present pages were read, see
+    /// [`MAX_FRAME_RUNS`].
+    pub truncated: bool,
+}
+
+impl Sharing {
+    pub fn read(residency: &Residency) -> AppResult<Self> {
+        // Short, it is shown in the info pane.
+        if residency.pfns_hidden() {
+            return Err("needs root".into());
+        }
+        let counts = File::open("/proc/kpagecount").map_err(needs_root)?;
+        let flags = File::open("/proc/kpageflags").map_err(needs_root)?;
+        let mut sharing = Self::default();
+        // Frames are read in runs of consecutive ones, which the pages of
+        // a mapping often are, rather than one by one.
+        let pfns: Vec<u64> = residency
+            .pages
+            .iter()
+            .filter(|page| page.present && page.pfn != 0)
+            .map(|page| page.pfn)
+            .collect();
+        let runs: Vec<&[u64]> = pfns.chunk_by(|a, b| *b == *a + 1).collect();
+        sharing.truncated = runs.len() > MAX_FRAME_RUNS;
+        for run in runs.into_iter().take(MAX_FRAME_RUNS) {
+            for count in read_frames(&counts, run)? {
+                *sharing.map_counts.entry(count).or_insert(0) += 1;
+            }
+            for page in read_frames(&flags, run)? {
+                let page = PhysicalPageFlags::from_bits_truncate(page);
+                let has = |flag| page.contains(flag) as usize;
+                sharing.thp += has(PhysicalPageFlags::THP);
+                sharing.ksm += has(PhysicalPageFlags::KSM);
+                sharing.dirty += has(PhysicalPageFlags::DIRTY);
+                sharing.active += has(PhysicalPageFlags::ACTIVE);
+            }
+        }
+        Ok(sharing)
+    }
+
+    /// `map_counts` in buckets doubling in size: 1, 2, 3-4, 5-8 and so on.
+    pub fn histogram(&self) -> Vec<(String, usize)> {
+        let mut buckets: Vec<(String, usize)> = Vec::new();
+        for (count, pages) in self.map_counts.iter() {
+            let label = match count {
+                0..=2 => count.to_string(),
+                _ => {
+                    let high = count.next_power_of_two();
+                    format!("{}-{}", high / 2 + 1, high)
+                }
+            };
+            match buckets.last_mut() {
+                Some((last, total)) if *last == label => *total += pages,
+                _ => buckets.push((label, *pages)),
+            }
+        }
+        buckets
+    }
+}
+
+/// The entries of `file` for a run of consecutive frames, in one read
+/// rather than through a buffer refilled on every seek.
+fn read_frames(file: &File, run: &[u64]) -> io::Result<Vec<u64>> {
+    let mut buf = vec![0; run.len() * 8];
+    file.read_exact_at(&mut buf, run[0] * 8)?;
+    Ok(buf
+        .chunks_exact(8)
+        .map(|entry| u64::from_ne_bytes(entry.try_into().unwrap()))
+        .collect())
+}
+
+/// Both files take CAP_SYS_ADMIN, i.e. EPERM or EACCES. Other errors are
+/// passed on as they are.
+fn needs_root(e: io::Error) -> Box<dyn std::error::Error> {
+    match e.kind() {
+        ErrorKind::PermissionDenied => "needs root".into(),
+        _ => e.into(),
+    }
+}
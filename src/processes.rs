@@ -0,0 +1,133 @@
+use procfs::process::Process;
+use std::fmt;
+
+/// A running process as listed by the process picker.
+#[derive(Clone, Debug)]
+pub struct ProcessSummary {
+    pub pid: i32,
+    pub comm: String,
+    pub cmdline: String,
+    pub rss: u64,
+    pub pss: u64,
+}
+
+impl ProcessSummary {
+    fn read(process: &Process, matching: &ProcessMatch) -> Option<Self> {
+        let comm = process.stat().ok()?.comm;
+        let cmdline = process.cmdline().unwrap_or_default();
+        // The rollup is the slow part, so it is only read for a match.
+        if !matching.matches(&comm, &cmdline) {
+            return None;
+        }
+        // Kernel threads have an empty rollup, there is nothing to explore.
+        let rollup = process.smaps_rollup().ok()?.memory_map_rollup.0.pop()?;
+        let field = |name: &str| *rollup.extension.map.get(name).unwrap_or(&0);
+        Some(Self {
+            pid: process.pid,
+            comm,
+            cmdline: cmdline.join(" "),
+            rss: field("Rss"),
+            pss: field("Pss"),
+        })
+    }
+}
+
+/// The processes `--name` and `--match` attach to. Both have to match when
+/// both are given, an empty one matches every process.
+#[derive(Clone, Debug, Default)]
+pub struct ProcessMatch {
+    /// The comm, or the file name of the executable in the cmdline.
+    pub name: Option<String>,
+    /// Text contained in the cmdline.
+    pub pattern: Option<String>,
+}
+
+impl ProcessMatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.pattern.is_none()
+    }
+
+    fn matches(&self, comm: &str, cmdline: &[String]) -> bool {
+        // The comm is cut at 15 characters, longer names only match the
+        // executable.
+        let executable = cmdline
+            .first()
+            .map(|arg0| arg0.rsplit('/').next().unwrap_or(arg0));
+        let name = self
+            .name
+            .as_ref()
+            .is_none_or(|name| comm == name || executable == Some(name.as_str()));
+        let pattern = self
+            .pattern
+            .as_ref()
+            .is_none_or(|pattern| cmdline.join(" ").contains(pattern.as_str()));
+        name && pattern
+    }
+}
+
+impl fmt::Display for ProcessMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut terms = Vec::new();
+        if let Some(name) = &self.name {
+            terms.push(format!("name '{}'", name));
+        }
+        if let Some(pattern) = &self.pattern {
+            terms.push(format!("cmdline '{}'", pattern));
+        }
+        write!(f, "{}", terms.join(" and "))
+    }
+}
+
+/// Every process matching `matching` whose smaps_rollup can be read.
+/// Processes that are gone or owned by another user without the rights to
+/// read them are skipped, and so is this one.
+pub fn processes(matching: &ProcessMatch) -> Vec<ProcessSummary> {
+    let Ok(all) = procfs::process::all_processes() else {
+        return Vec::new();
+    };
+    let own_pid = std::process::id() as i32;
+    all.flatten()
+        .filter(|process| process.pid != own_pid)
+        .filter_map(|process| ProcessSummary::read(&process, matching))
+        .collect()
+}
+
+/// Orders of the process picker, cycled through with tab.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProcessOrder {
+    #[default]
+    Pss,
+    Rss,
+    Pid,
+    Comm,
+}
+
+impl ProcessOrder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessOrder::Pss => "Pss",
+            ProcessOrder::Rss => "Rss",
+            ProcessOrder::Pid => "pid",
+            ProcessOrder::Comm => "comm",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            ProcessOrder::Pss => ProcessOrder::Rss,
+            ProcessOrder::Rss => ProcessOrder::Pid,
+            ProcessOrder::Pid => ProcessOrder::Comm,
+            ProcessOrder::Comm => ProcessOrder::Pss,
+        }
+    }
+
+    /// Memory is listed largest first, pids and names in ascending order.
+    pub fn sort(&self, processes: &mut [ProcessSummary]) {
+        match self {
+            ProcessOrder::Pss => processes.sort_by_key(|p| std::cmp::Reverse(p.pss)),
+            ProcessOrder::Rss => processes.sort_by_key(|p| std::cmp::Reverse(p.rss)),
+            ProcessOrder::Pid => processes.sort_by_key(|p| p.pid),
+            ProcessOrder::Comm => processes.sort_by(|a, b| a.comm.cmp(&b.comm)),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::app::{mmpath_to_string, AppResult, MemoryMapMatrix, Metric, UNSUMMABLE_FIELDS};
+use humansize::{format_size, DECIMAL};
+use procfs::process::MMapPath::Rollup;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Print the rollup totals followed by the `top` path groups ranked by
+/// `metric` as a plain text table.
+///
+/// Saved smaps files usually come without a rollup, in which case the
+/// totals are summed up from the mappings.
+pub fn report(
+    memory_maps: &MemoryMapMatrix,
+    metric: Metric,
+    top: usize,
+    mut out: impl Write,
+) -> AppResult<()> {
+    let (rollup, mut groups): (Vec<_>, Vec<_>) = memory_maps
+        .iter()
+        .partition(|group| group[0].pathname == Rollup);
+
+    let mut totals: BTreeMap<&str, u64> = BTreeMap::new();
+    match rollup.first() {
+        Some(rollup) => {
+            writeln!(out, "Rollup")?;
+            for (k, v) in rollup[0].extension.map.iter() {
+                totals.insert(k, *v);
+            }
+        }
+        None => {
+            writeln!(out, "Totals")?;
+            for mm in groups.iter().flat_map(|group| group.iter()) {
+                for (k, v) in mm.extension.map.iter() {
+                    *totals.entry(k).or_default() += v;
+                }
+            }
+        }
+    }
+    for (k, v) in totals
+        .iter()
+        .filter(|(k, _)| !UNSUMMABLE_FIELDS.contains(k))
+    {
+        writeln!(
+            out,
+            "  {:<18}{:>12}",
+            k.to_lowercase(),
+            format_size(*v, DECIMAL)
+        )?;
+    }
+    writeln!(out)?;
+
+    groups.sort_by_key(|group| Reverse(metric.sum(group)));
+    writeln!(
+        out,
+        "Top {} paths by {}",
+        top.min(groups.len()),
+        metric.name().to_lowercase()
+    )?;
+    writeln!(
+        out,
+        "{:>12}{:>12}{:>12}{:>16}{:>10}  PATH",
+        "RSS", "PSS", "SWAP", "PRIVATE_DIRTY", "SEGMENTS"
+    )?;
+    for group in groups.iter().take(top) {
+        writeln!(
+            out,
+            "{:>12}{:>12}{:>12}{:>16}{:>10}  {}",
+            format_size(Metric::Rss.sum(group), DECIMAL),
+            format_size(Metric::Pss.sum(group), DECIMAL),
+            format_size(Metric::Swap.sum(group), DECIMAL),
+            format_size(Metric::PrivateDirty.sum(group), DECIMAL),
+            group.len(),
+            mmpath_to_string(&group[0].pathname)
+        )?;
+    }
+    Ok(())
+}
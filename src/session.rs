@@ -0,0 +1,244 @@
+use crate::app::{mmpath_to_string, App, AppResult, Grouping, SmapsSource};
+use crate::processes::ProcessMatch;
+use crate::ui::{CompareWidget, ProcessPickerWidget, ThreadsWidget, WhoMapsWidget};
+use crate::whomaps::FileTarget;
+use log::*;
+use std::time::Duration;
+
+/// Every process open in the tui, one [`App`] per tab, and the process
+/// picker used to add more.
+pub struct Session {
+    running: bool,
+    debug: bool,
+    /// Applied to every tab opened from the picker.
+    refresh_interval: Option<Duration>,
+    grouping: Grouping,
+    pub apps: Vec<App>,
+    /// Index into `apps` of the tab on screen.
+    pub current: usize,
+    /// The tab shown before the current one, what it is compared with.
+    previous: usize,
+    /// Shown over the tabs while set.
+    pub picker: Option<ProcessPickerWidget>,
+    /// Shown in place of the tab while set.
+    pub compare: Option<CompareWidget>,
+    /// Indexes into `apps` of the tabs being compared.
+    compared: (usize, usize),
+    /// Shown in place of the tab while set.
+    pub who_maps: Option<WhoMapsWidget>,
+    /// Shown in place of the tab while set.
+    pub threads: Option<ThreadsWidget>,
+}
+
+impl Session {
+    pub fn new(debug: bool, refresh_interval: Option<Duration>, grouping: Grouping) -> Self {
+        Self {
+            running: true,
+            debug,
+            refresh_interval,
+            grouping,
+            apps: Vec::new(),
+            current: 0,
+            previous: 0,
+            picker: None,
+            compare: None,
+            compared: (0, 0),
+            who_maps: None,
+            threads: None,
+        }
+    }
+
+    /// Open `source` in a new tab and switch to it.
+    pub fn open(&mut self, source: SmapsSource) -> AppResult<()> {
+        let app = App::new(source, self.debug)?;
+        self.push(app);
+        Ok(())
+    }
+
+    /// Add an already loaded `app` as a new tab and switch to it.
+    pub fn push(&mut self, mut app: App) {
+        app.refresh_interval = self.refresh_interval;
+        app.set_grouping(self.grouping);
+        self.apps.push(app);
+        self.select(self.apps.len() - 1);
+    }
+
+    pub fn app(&mut self) -> Option<&mut App> {
+        self.apps.get_mut(self.current)
+    }
+
+    /// Switch to the tab at `idx`, if there is one.
+    pub fn select(&mut self, idx: usize) {
+        if idx < self.apps.len() && idx != self.current {
+            self.previous = self.current;
+            self.current = idx;
+        }
+    }
+
+    /// Close the tab on screen. The last one stays open.
+    pub fn close(&mut self) {
+        if self.apps.len() > 1 {
+            self.apps.remove(self.current);
+            self.current = self.current.min(self.apps.len() - 1);
+            self.previous = self.current;
+        }
+    }
+
+    /// Compare the tab on screen with the one shown before it, or with the
+    /// next one if there is no such tab. Closes the comparison if open.
+    pub fn toggle_compare(&mut self) {
+        if self.compare.take().is_some() || self.apps.len() < 2 {
+            return;
+        }
+        let other = match self.previous {
+            previous if previous != self.current && previous < self.apps.len() => previous,
+            _ => (self.current + 1) % self.apps.len(),
+        };
+        let (left, right) = (&self.apps[self.current], &self.apps[other]);
+        self.compare = Some(CompareWidget::new(
+            left.name.clone(),
+            left.snapshot(),
+            right.name.clone(),
+            right.snapshot(),
+        ));
+        self.compared = (self.current, other);
+    }
+
+    /// List every process mapping the file of the path selected in the tab
+    /// on screen. Closes the list if open.
+    pub fn toggle_who_maps(&mut self) {
+        if self.who_maps.take().is_some() {
+            return;
+        }
+        let Some(app) = self.app() else {
+            return;
+        };
+        let Some(segments) = app.path_list_widget.selected_segments() else {
+            return;
+        };
+        // Matched on dev:inode, so the copies of the file other processes
+        // see under another path, e.g. in a container, are found too.
+        match segments
+            .iter()
+            .find_map(|mm| Some((FileTarget::of(mm)?, mmpath_to_string(&mm.pathname))))
+        {
+            Some((target, label)) => self.who_maps = Some(WhoMapsWidget::new(label, target)),
+            None => warn!(target:"App", "Only file mappings can be looked up"),
+        }
+    }
+
+    /// Open the process selected in the list of who maps a file in a new
+    /// tab.
+    pub fn open_who_maps_selected(&mut self) {
+        let Some(pid) = self.who_maps.as_ref().and_then(WhoMapsWidget::selected_pid) else {
+            return;
+        };
+        match App::new(SmapsSource::Pid(pid), self.debug) {
+            Ok(app) => {
+                self.who_maps = None;
+                self.push(app);
+            }
+            Err(e) => {
+                if let Some(who_maps) = self.who_maps.as_mut() {
+                    who_maps.message(format!("Failed to open pid {}: {}", pid, e));
+                }
+            }
+        }
+    }
+
+    /// List the threads of the process on screen. Closes the list if open.
+    pub fn toggle_threads(&mut self) {
+        if self.threads.take().is_some() {
+            return;
+        }
+        let Some(app) = self.app() else {
+            return;
+        };
+        match app.pid() {
+            Some(pid) => {
+                self.threads = Some(ThreadsWidget::new(
+                    pid,
+                    app.name.clone(),
+                    app.threads.clone(),
+                ))
+            }
+            None => warn!(target:"App", "Only a running process has threads to list"),
+        }
+    }
+
+    /// Open the thread selected in the list of threads in a new tab.
+    pub fn open_threads_selected(&mut self) {
+        let Some((pid, tid)) = self.threads.as_ref().and_then(ThreadsWidget::selected) else {
+            return;
+        };
+        match App::new(SmapsSource::Thread(pid, tid), self.debug) {
+            Ok(app) => {
+                self.threads = None;
+                self.push(app);
+            }
+            Err(e) => {
+                if let Some(threads) = self.threads.as_mut() {
+                    threads.message(format!("Failed to open thread {}: {}", tid, e));
+                }
+            }
+        }
+    }
+
+    pub fn open_picker(&mut self, matching: ProcessMatch) {
+        self.picker = Some(ProcessPickerWidget::new(matching));
+    }
+
+    /// Open the process picked in the picker, or close the picker once it
+    /// was quit. Quitting it without any tab open ends the session.
+    pub fn update_picker(&mut self) {
+        let Some(picker) = self.picker.as_mut() else {
+            return;
+        };
+        if let Some(pid) = picker.take_picked() {
+            match App::new(SmapsSource::Pid(pid), self.debug) {
+                Ok(app) => {
+                    self.picker = None;
+                    self.push(app);
+                }
+                Err(e) => picker.message(format!("Failed to open pid {}: {}", pid, e)),
+            }
+        } else if !picker.running() {
+            self.picker = None;
+            if self.apps.is_empty() {
+                self.quit();
+            }
+        }
+    }
+
+    /// Handles the tick event of the terminal. Only the tab on screen is
+    /// refreshed, or the two compared, the others catch up once shown.
+    pub fn tick(&mut self) {
+        match self.compare.as_mut() {
+            Some(compare) => {
+                let (left, right) = self.compared;
+                self.apps[left].tick();
+                if right != left {
+                    self.apps[right].tick();
+                }
+                compare.update(self.apps[left].snapshot(), self.apps[right].snapshot());
+            }
+            None => {
+                if let Some(app) = self.apps.get_mut(self.current) {
+                    app.tick();
+                }
+            }
+        }
+        if let Some(picker) = self.picker.as_mut() {
+            picker.tick();
+        }
+    }
+
+    pub fn quit(&mut self) {
+        self.running = false;
+    }
+
+    /// Quitting any tab ends the session.
+    pub fn running(&self) -> bool {
+        self.running && self.apps.iter().all(App::running)
+    }
+}
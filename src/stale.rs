@@ -0,0 +1,149 @@
+use crate::app::{mmpath_to_string, AppResult, MemoryMapMatrix};
+use procfs::process::{MMapPath, MemoryMap};
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Files that are deleted while mapped by design, rather than left behind by
+/// an upgrade. SysV shared memory and GPU buffers have an inode of their own.
+const EPHEMERAL_PREFIXES: [&str; 7] = [
+    "/memfd:",
+    "/dev/zero",
+    "/dev/shm/",
+    "/[aio]",
+    "/anon_hugepage",
+    "/SYSV",
+    "/drm mm object",
+];
+
+/// Where package managers install what processes map. `needs-restart` takes
+/// a deleted file anywhere else to be the process's own business.
+const UPGRADED_PREFIXES: [&str; 8] = [
+    "/usr/", "/lib/", "/lib32/", "/lib64/", "/bin/", "/sbin/", "/opt/", "/snap/",
+];
+
+const DELETED_SUFFIX: &str = " (deleted)";
+
+/// Why a file mapping no longer matches the file on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Staleness {
+    /// The file was removed, smaps marks its path ` (deleted)`.
+    Deleted,
+    /// Another file took its place, e.g. a library after a package upgrade.
+    Replaced,
+}
+
+impl Staleness {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Staleness::Deleted => "deleted",
+            Staleness::Replaced => "replaced",
+        }
+    }
+}
+
+/// Whether `mm` maps an old copy of its file. The file on disk is only
+/// looked at with a `root` to resolve the path in, the root directory of
+/// the process, as a saved smaps file may come from another host.
+pub fn staleness(mm: &MemoryMap, root: Option<&Path>) -> Option<Staleness> {
+    let MMapPath::Path(path) = &mm.pathname else {
+        return None;
+    };
+    if mm.inode == 0 {
+        return None;
+    }
+    let name = path.to_string_lossy();
+    if EPHEMERAL_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+    {
+        return None;
+    }
+    if name.ends_with(DELETED_SUFFIX) {
+        return Some(Staleness::Deleted);
+    }
+    let root = root?;
+    let metadata = root.join(path.strip_prefix("/").unwrap_or(path)).metadata();
+    // Only the inode is compared: on overlayfs the file reports the device
+    // of the overlay while smaps shows the one of the layer below.
+    match metadata {
+        Ok(metadata) if metadata.ino() != mm.inode => Some(Staleness::Replaced),
+        _ => None,
+    }
+}
+
+/// The staleness of each group, from the first stale mapping in it.
+pub fn stale_groups(memory_maps: &MemoryMapMatrix, root: Option<&Path>) -> Vec<Option<Staleness>> {
+    memory_maps
+        .iter()
+        .map(|group| group.iter().find_map(|mm| staleness(mm, root)))
+        .collect()
+}
+
+/// A process still mapping old copies of files.
+#[derive(Clone, Debug)]
+pub struct StaleProcess {
+    pub pid: i32,
+    pub comm: String,
+    /// Every stale file once, without the ` (deleted)` mark.
+    pub paths: Vec<(String, Staleness)>,
+}
+
+/// Every readable process mapping deleted or replaced files, i.e. the ones
+/// to restart after an upgrade. Only files deleted from where packages
+/// install count.
+pub fn scan() -> Vec<StaleProcess> {
+    let Ok(all) = procfs::process::all_processes() else {
+        return Vec::new();
+    };
+    all.flatten()
+        .filter_map(|process| {
+            let root = PathBuf::from(format!("/proc/{}/root", process.pid));
+            let mut paths: Vec<(String, Staleness)> = Vec::new();
+            for mm in process.maps().ok()? {
+                let Some(staleness) = staleness(&mm, Some(&root)) else {
+                    continue;
+                };
+                let path = mmpath_to_string(&mm.pathname);
+                let upgraded = UPGRADED_PREFIXES
+                    .iter()
+                    .any(|prefix| path.starts_with(prefix));
+                if staleness == Staleness::Deleted && !upgraded {
+                    continue;
+                }
+                let path = path.strip_suffix(DELETED_SUFFIX).unwrap_or(&path);
+                if !paths.iter().any(|(seen, _)| seen == path) {
+                    paths.push((path.to_string(), staleness));
+                }
+            }
+            if paths.is_empty() {
+                return None;
+            }
+            Some(StaleProcess {
+                pid: process.pid,
+                comm: process.stat().map(|stat| stat.comm).unwrap_or_default(),
+                paths,
+            })
+        })
+        .collect()
+}
+
+/// Print every process mapping stale files, one line per file.
+pub fn report(mut out: impl Write) -> AppResult<()> {
+    let processes = scan();
+    writeln!(out, "{:>8}  {:<16}{:<10}PATH", "PID", "COMM", "STATUS")?;
+    for process in processes.iter() {
+        for (path, staleness) in process.paths.iter() {
+            writeln!(
+                out,
+                "{:>8}  {:<16}{:<10}{}",
+                process.pid,
+                process.comm,
+                staleness.name(),
+                path
+            )?;
+        }
+    }
+    writeln!(out, "{} processes need a restart", processes.len())?;
+    Ok(())
+}
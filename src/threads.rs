@@ -0,0 +1,128 @@
+use crate::app::{AppResult, MemoryMapMatrix};
+use crate::lookup::{self, Lookup};
+use humansize::{format_size, DECIMAL};
+use procfs::process::MMapPath::{Stack, TStack};
+use procfs::process::Process;
+use std::fs;
+
+/// A thread of a process and the mapping holding its stack.
+#[derive(Clone, Debug)]
+pub struct ThreadStack {
+    pub tid: i32,
+    /// From `task/<tid>/comm`, as set with pthread_setname_np.
+    pub name: String,
+    /// Where the stack pointer was when the thread was read, unless it was
+    /// running.
+    pub stack_pointer: Option<u64>,
+    /// Group and segment index of the stack mapping, if it was found.
+    pub stack: Option<(usize, usize)>,
+    /// Size and Rss of the stack mapping.
+    pub size: u64,
+    pub rss: u64,
+}
+
+/// Every thread of `pid`, to find the stacks of with [`locate_stacks`].
+pub fn read_threads(pid: i32) -> AppResult<Vec<ThreadStack>> {
+    let mut threads: Vec<ThreadStack> = Process::new(pid)?
+        .tasks()?
+        .flatten()
+        .map(|task| {
+            let comm = fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, task.tid));
+            ThreadStack {
+                tid: task.tid,
+                name: comm.unwrap_or_default().trim_end().to_string(),
+                stack_pointer: stack_pointer(pid, task.tid),
+                stack: None,
+                size: 0,
+                rss: 0,
+            }
+        })
+        .collect();
+    threads.sort_by_key(|thread| thread.tid);
+    Ok(threads)
+}
+
+/// Find the stack of every thread in `memory_maps`, again whenever they are
+/// sorted or grouped differently.
+///
+/// Kernels before 4.5 label thread stacks `[stack:<tid>]`. Later ones only
+/// label the stack of the main thread, the others are found by the stack
+/// pointer in `task/<tid>/syscall`, which takes the same rights as smaps.
+pub fn locate_stacks(threads: &mut [ThreadStack], pid: i32, memory_maps: &MemoryMapMatrix) {
+    for thread in threads.iter_mut() {
+        thread.stack =
+            labelled_stack(memory_maps, pid, thread.tid).or_else(|| {
+                match lookup::lookup(memory_maps, thread.stack_pointer?) {
+                    Lookup::Mapped(group, segment) => Some((group, segment)),
+                    Lookup::Hole { .. } => None,
+                }
+            });
+        let field = |key: &str| -> u64 {
+            thread
+                .stack
+                .and_then(|(group, segment)| {
+                    memory_maps[group][segment].extension.map.get(key).copied()
+                })
+                .unwrap_or(0)
+        };
+        thread.size = field("Size");
+        thread.rss = field("Rss");
+    }
+}
+
+fn labelled_stack(memory_maps: &MemoryMapMatrix, pid: i32, tid: i32) -> Option<(usize, usize)> {
+    memory_maps.iter().enumerate().find_map(|(group, maps)| {
+        let segment = maps.iter().position(|mm| match mm.pathname {
+            TStack(stack_tid) => stack_tid as i32 == tid,
+            Stack => tid == pid,
+            _ => false,
+        })?;
+        Some((group, segment))
+    })
+}
+
+/// The stack pointer of a thread, the second to last field of its syscall
+/// file, e.g. `202 0x55d0 0x80 0x0 0x0 0x0 0x0 0x7ffd3a58 0x7f9c`. Running
+/// threads have none.
+fn stack_pointer(pid: i32, tid: i32) -> Option<u64> {
+    let syscall = fs::read_to_string(format!("/proc/{}/task/{}/syscall", pid, tid)).ok()?;
+    let fields: Vec<&str> = syscall.split_whitespace().collect();
+    if fields.len() < 3 {
+        return None;
+    }
+    let sp = fields[fields.len() - 2];
+    u64::from_str_radix(sp.strip_prefix("0x")?, 16).ok()
+}
+
+/// The process a thread belongs to.
+pub fn thread_group(tid: i32) -> AppResult<i32> {
+    Ok(Process::new(tid)?.status()?.tgid)
+}
+
+/// A note for each group holding thread stacks, with their Rss next to
+/// their size, e.g. `stack of worker (1234), rss 16.38 kB of 8.39 MB`.
+pub fn stack_labels(threads: &[ThreadStack], groups: usize) -> Vec<Option<String>> {
+    let mut labels = vec![None; groups];
+    for (group, label) in labels.iter_mut().enumerate() {
+        let stacks: Vec<&ThreadStack> = threads
+            .iter()
+            .filter(|thread| thread.stack.is_some_and(|(g, _)| g == group))
+            .collect();
+        let rss: u64 = stacks.iter().map(|thread| thread.rss).sum();
+        let size: u64 = stacks.iter().map(|thread| thread.size).sum();
+        *label = match stacks.as_slice() {
+            [] => None,
+            [thread] => Some(format!("stack of {} ({})", thread.name, thread.tid)),
+            stacks => Some(format!("stacks of {} threads", stacks.len())),
+        }
+        .map(|label| {
+            format!(
+                "{}, rss {} of {}",
+                label,
+                format_size(rss, DECIMAL),
+                format_size(size, DECIMAL)
+            )
+        });
+    }
+    labels
+}
@@ -0,0 +1,169 @@
+use crate::app::{AppResult, MemoryMapMatrix, SmapsSource};
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SNAPSHOT_HEADER: &str = "# smaps-explorer snapshot";
+
+/// Append a snapshot of `pid` to `path` every `interval` until `count`
+/// snapshots were taken or the process goes away.
+///
+/// Every snapshot is written as its own gzip member holding a header line
+/// with the unix timestamp followed by the raw smaps_rollup and smaps text.
+/// Members are complete once written, so the file stays readable if the
+/// recording is interrupted.
+pub fn record(pid: i32, path: &Path, interval: Duration, count: Option<usize>) -> AppResult<()> {
+    let process = procfs::process::Process::new(pid)?;
+    let mut taken = 0;
+    while count.is_none_or(|count| taken < count) {
+        let mut text = String::new();
+        let read = process
+            .open_relative("smaps_rollup")
+            .and_then(|mut f| Ok(f.read_to_string(&mut text)?))
+            .and_then(|_| process.open_relative("smaps"))
+            .and_then(|mut f| Ok(f.read_to_string(&mut text)?));
+        if let Err(e) = read {
+            eprintln!("stopped recording pid {}: {}", pid, e);
+            break;
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        writeln!(encoder, "{} {}", SNAPSHOT_HEADER, timestamp)?;
+        encoder.write_all(text.as_bytes())?;
+        encoder.finish()?;
+
+        taken += 1;
+        eprintln!("recorded snapshot {} of pid {}", taken, pid);
+        if count.is_none_or(|count| taken < count) {
+            thread::sleep(interval);
+        }
+    }
+    Ok(())
+}
+
+/// The snapshots of a file written by [`record`] and the one being shown.
+///
+/// Snapshots are kept compressed and only decoded when stepped to, so long
+/// recordings don't have to fit in memory as parsed maps.
+#[derive(Debug)]
+pub struct Timeline {
+    path: PathBuf,
+    data: Vec<u8>,
+    snapshots: Vec<(u64, Range<usize>)>,
+    position: usize,
+}
+
+impl Timeline {
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let mut timeline = Self {
+            path: path.to_path_buf(),
+            data: Vec::new(),
+            snapshots: Vec::new(),
+            position: 0,
+        };
+        timeline.reload()?;
+        Ok(timeline)
+    }
+
+    /// Re-read the file to pick up snapshots appended since it was loaded.
+    /// If the last snapshot was shown, the newest one is shown afterwards.
+    pub fn reload(&mut self) -> AppResult<()> {
+        let follow = !self.snapshots.is_empty() && self.position == self.snapshots.len() - 1;
+        self.data = std::fs::read(&self.path)?;
+        self.snapshots.clear();
+
+        let mut offset = 0;
+        while offset < self.data.len() {
+            let mut decoder = GzDecoder::new(&self.data[offset..]);
+            let mut text = String::new();
+            // A recording that was killed mid-write leaves a truncated last
+            // member behind; everything before it is still usable.
+            if decoder.read_to_string(&mut text).is_err() {
+                break;
+            }
+            let end = self.data.len() - decoder.into_inner().len();
+            self.snapshots.push((parse_header(&text)?, offset..end));
+            offset = end;
+        }
+
+        if self.snapshots.is_empty() {
+            return Err(format!("no snapshots in {}", self.path.display()).into());
+        }
+        self.position = if follow {
+            self.snapshots.len() - 1
+        } else {
+            self.position.min(self.snapshots.len() - 1)
+        };
+        Ok(())
+    }
+
+    /// Decode and parse the snapshot at the current position.
+    pub fn memory_maps(&self) -> AppResult<MemoryMapMatrix> {
+        self.memory_maps_at(self.position)
+    }
+
+    /// Decode and parse the newest snapshot.
+    pub fn last_memory_maps(&self) -> AppResult<MemoryMapMatrix> {
+        self.memory_maps_at(self.snapshots.len() - 1)
+    }
+
+    fn memory_maps_at(&self, position: usize) -> AppResult<MemoryMapMatrix> {
+        let range = self.snapshots[position].1.clone();
+        let mut text = String::new();
+        GzDecoder::new(&self.data[range]).read_to_string(&mut text)?;
+        // Skip the header line, the rest is plain smaps text.
+        let smaps = text.split_once('\n').map_or("", |(_, smaps)| smaps);
+        SmapsSource::Text(smaps.to_string()).load()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Seconds between the first snapshot and the one at the current position.
+    pub fn elapsed(&self) -> u64 {
+        self.snapshots[self.position]
+            .0
+            .saturating_sub(self.snapshots[0].0)
+    }
+
+    pub fn next(&mut self) {
+        self.position = (self.position + 1).min(self.snapshots.len() - 1);
+    }
+
+    pub fn previous(&mut self) {
+        self.position = self.position.saturating_sub(1);
+    }
+
+    pub fn first(&mut self) {
+        self.position = 0;
+    }
+
+    pub fn last(&mut self) {
+        self.position = self.snapshots.len() - 1;
+    }
+}
+
+fn parse_header(text: &str) -> AppResult<u64> {
+    let line = text.lines().next().unwrap_or_default();
+    let timestamp = line
+        .strip_prefix(SNAPSHOT_HEADER)
+        .ok_or_else(|| format!("not a smaps-explorer recording: '{}'", line))?;
+    Ok(timestamp.trim().parse()?)
+}
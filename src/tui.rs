@@ -1,5 +1,6 @@
-use crate::app::{App, AppResult};
+use crate::app::AppResult;
 use crate::event::EventHandler;
+use crate::session::Session;
 use crate::ui;
 use ratatui::backend::Backend;
 use ratatui::crossterm::event::{DisableMouseCapture, EnableMouseCapture};
@@ -50,8 +51,8 @@ impl<B: Backend> Tui<B> {
     ///
     /// [`Draw`]: ratatui::Terminal::draw
     /// [`rendering`]: crate::ui::render
-    pub fn draw(&mut self, app: &mut App) -> AppResult<()> {
-        self.terminal.draw(|frame| ui::render(app, frame))?;
+    pub fn draw(&mut self, session: &mut Session) -> AppResult<()> {
+        self.terminal.draw(|frame| ui::render(session, frame))?;
         Ok(())
     }
 
@@ -1,27 +1,45 @@
-use crate::app::{self, App, MemoryMapMatrix};
+use crate::app::{self, App, Category, MemoryMapMatrix, Metric};
+use crate::cgroup::CgroupStats;
+use crate::compare::{self, Comparison, PathComparison};
+use crate::diff::{DiffStatus, SnapshotDiff};
+use crate::filter::{self, Query};
+use crate::lookup;
+use crate::pagemap::{Page, Residency, Sharing};
+use crate::processes::{self, ProcessMatch, ProcessOrder, ProcessSummary};
+use crate::session::Session;
+use crate::stale::Staleness;
+use crate::threads::ThreadStack;
+use crate::timeline::Timeline;
+use crate::whomaps::{self, FileTarget, FileUser};
 use humansize::{format_size, DECIMAL};
 use itertools::Itertools;
-use log::LevelFilter;
+use log::{warn, LevelFilter};
 use nucleo::pattern::{CaseMatching, Normalization};
 use nucleo::{Config, Nucleo, Utf32String};
-use procfs::process::MemoryMap;
+use procfs::process::{MMapPath, MemoryMap};
 use ratatui::{
     prelude::*,
     style::Style,
     widgets::{
-        Block, BorderType, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table,
-        TableState, Widget,
+        Block, BorderType, Borders, Clear, LineGauge, List, ListItem, ListState, Paragraph, Row,
+        Table, TableState, Tabs, Widget,
     },
     Frame,
 };
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::thread::available_parallelism;
 use tui_logger::{TuiLoggerLevelOutput, TuiLoggerWidget, TuiWidgetState};
+use tui_tree_widget::{Tree, TreeItem, TreeState};
 
 #[derive(Clone, Debug)]
 pub struct SegmentTableWidget {
     memory_maps: Rc<MemoryMapMatrix>,
+    title: String,
+    diff: Option<Rc<SnapshotDiff>>,
     selected_identifier: Option<usize>,
     state: TableState,
     active_pane: bool,
@@ -31,12 +49,19 @@ impl SegmentTableWidget {
     pub fn new(memory_map_matrix: Rc<MemoryMapMatrix>) -> Self {
         Self {
             memory_maps: memory_map_matrix,
+            title: "Segment".to_string(),
+            diff: None,
             selected_identifier: None,
             state: TableState::default().with_selected(0),
             active_pane: false,
         }
     }
 
+    fn with_title(mut self, title: String) -> Self {
+        self.title = title;
+        self
+    }
+
     fn render_memory_widget(
         &mut self,
         layout: Rect,
@@ -57,21 +82,16 @@ impl SegmentTableWidget {
     }
 
     pub fn next(&mut self) {
-        if let Some(v) = self.state.selected() {
-            let outer = self.selected_identifier.unwrap_or(0);
-            let idx = (v + 1) % self.memory_maps[outer].len();
-            self.state.select(Some(idx));
+        let len = self.segments().len();
+        if let (Some(v), true) = (self.state.selected(), len > 0) {
+            self.state.select(Some((v + 1) % len));
         };
     }
 
     pub fn previous(&mut self) {
-        if let Some(v) = self.state.selected() {
-            let outer = self.selected_identifier.unwrap_or(0);
-            let idx = if v == 0 {
-                self.memory_maps[outer].len() - 1
-            } else {
-                v - 1
-            };
+        let len = self.segments().len();
+        if let (Some(v), true) = (self.state.selected(), len > 0) {
+            let idx = if v == 0 { len - 1 } else { v - 1 };
             self.state.select(Some(idx));
         };
     }
@@ -81,9 +101,10 @@ impl SegmentTableWidget {
     }
 
     pub fn go_bottom(&mut self) {
-        let outer = self.selected_identifier.unwrap_or(0);
-        let idx = self.memory_maps[outer].len() - 1;
-        self.state.select(Some(idx));
+        let len = self.segments().len();
+        if len > 0 {
+            self.state.select(Some(len - 1));
+        }
     }
 
     pub fn reset_select(&mut self) {
@@ -91,13 +112,67 @@ impl SegmentTableWidget {
     }
 
     fn selected_identifier(&mut self, id: Option<usize>) {
+        // A different group may have fewer segments than the selected row.
+        if id != self.selected_identifier {
+            self.reset_select();
+        }
         self.selected_identifier = id;
     }
 
+    pub fn select_segment(&mut self, selected_identifier: usize, idx: usize) {
+        self.selected_identifier = Some(selected_identifier);
+        self.state.select(Some(idx));
+    }
+
+    /// Swap in freshly read memory maps. The selection stays on the segment
+    /// with the same address range, or the same start address if the
+    /// segment has grown or shrunk since the last read.
+    pub fn update(
+        &mut self,
+        memory_map_matrix: Rc<MemoryMapMatrix>,
+        diff: Option<Rc<SnapshotDiff>>,
+        selected_identifier: Option<usize>,
+    ) {
+        let previous = self.selected_segment();
+        self.memory_maps = memory_map_matrix;
+        self.diff = diff;
+        self.selected_identifier = selected_identifier;
+        let segments = self.segments();
+        let idx = previous
+            .and_then(|prev| {
+                segments
+                    .iter()
+                    .position(|mm| mm.address == prev.address)
+                    .or_else(|| {
+                        segments
+                            .iter()
+                            .position(|mm| mm.address.0 == prev.address.0)
+                    })
+            })
+            .unwrap_or(0);
+        let idx = (!segments.is_empty()).then_some(idx);
+        self.state.select(idx);
+    }
+
+    /// The segments of the selected group, none while no group is.
+    fn segments(&self) -> &[MemoryMap] {
+        self.selected_identifier
+            .and_then(|outer| self.memory_maps.get(outer))
+            .map_or(&[], |segments| segments.as_slice())
+    }
+
+    /// Group and segment index of the selected row.
+    pub fn selected_indexes(&self) -> (usize, usize) {
+        (
+            self.selected_identifier.unwrap_or(0),
+            self.state.selected().unwrap_or(0),
+        )
+    }
+
+    /// The selected segment, none while the path list has no rows.
     fn selected_segment(&self) -> Option<MemoryMap> {
-        let outer = self.selected_identifier.unwrap_or(0);
         let inner = self.state.selected().unwrap_or(0);
-        Some(self.memory_maps[outer][inner].clone())
+        self.segments().get(inner).cloned()
     }
 }
 
@@ -105,36 +180,46 @@ impl Widget for &mut SegmentTableWidget {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let outer_key = self.selected_identifier.unwrap_or(0);
         let mut rows = Vec::new();
-        for mm in self.memory_maps[outer_key].iter() {
+        for (idx, mm) in self.segments().iter().enumerate() {
             let size = *mm.extension.map.get("Size").unwrap_or(&0);
             let rss = *mm.extension.map.get("Rss").unwrap_or(&0);
             let start_addr = format!("{:#x}", mm.address.0);
             let end_addr = format!("{:#x}", mm.address.1);
-            rows.push(Row::new(vec![
+            let mut cells = vec![
                 start_addr,
                 end_addr,
                 format_size(size, DECIMAL),
                 format_size(rss, DECIMAL),
-            ]));
+            ];
+            let row = match &self.diff {
+                Some(diff) => {
+                    let delta = diff.segments[outer_key][idx];
+                    cells.push(format_delta(delta.rss));
+                    cells.push(format_delta(delta.pss));
+                    cells.push(format_delta(delta.swap));
+                    Row::new(cells).style(diff_status_color(delta.status))
+                }
+                None => Row::new(cells),
+            };
+            rows.push(row);
         }
 
-        let widths = vec![
-            Constraint::Length(25),
-            Constraint::Length(25),
-            Constraint::Length(25),
-            Constraint::Length(25),
-        ];
+        let mut header = vec!["Start", "End", "Size", "RSS"];
+        if self.diff.is_some() {
+            header.extend(["ΔRSS", "ΔPSS", "ΔSwap"]);
+        }
+        let widths = vec![Constraint::Length(25); header.len()];
 
         let table = Table::new(rows, widths)
             .block(
                 Block::bordered()
-                    .title_top("Segment")
+                    .title_top(self.title.clone())
                     .title_style(selected_pane_color(&self.active_pane))
                     .title_alignment(Alignment::Center)
                     .border_style(selected_pane_color(&self.active_pane)),
             )
             .highlight_style(Style::new().light_yellow())
-            .header(Row::new(vec!["Start", "End", "Size", "RSS"]).style(Style::new().bold()));
+            .header(Row::new(header).style(Style::new().bold()));
 
         StatefulWidget::render(table, area, buf, &mut self.state)
     }
@@ -143,6 +228,8 @@ impl Widget for &mut SegmentTableWidget {
 #[derive(Clone, Debug)]
 pub struct InfoWidget {
     selected_segment: Option<MemoryMap>,
+    /// How the frames of the segment are shared, while its pages are shown.
+    sharing: Option<Result<Sharing, String>>,
 }
 
 impl Default for InfoWidget {
@@ -155,6 +242,7 @@ impl InfoWidget {
     pub fn new() -> Self {
         Self {
             selected_segment: None,
+            sharing: None,
         }
     }
 
@@ -163,8 +251,10 @@ impl InfoWidget {
         layout: Rect,
         frame: &mut Frame,
         selected_segment: Option<MemoryMap>,
+        sharing: Option<Result<Sharing, String>>,
     ) {
         self.selected_segments(selected_segment);
+        self.sharing = sharing;
         frame.render_widget(self, layout);
     }
 
@@ -202,6 +292,11 @@ impl Widget for &mut InfoWidget {
                         format_size(v, DECIMAL),
                     ]));
                 }
+                match &self.sharing {
+                    Some(Ok(sharing)) => rows.extend(sharing_rows(sharing)),
+                    Some(Err(e)) => rows.push(Row::new(["page sharing".to_string(), e.clone()])),
+                    None => {}
+                }
                 let widths = vec![Constraint::Percentage(50); 2];
                 let widget = Table::new(rows, widths).block(
                     Block::bordered()
@@ -224,6 +319,79 @@ impl Widget for &mut InfoWidget {
     }
 }
 
+/// The info pane section on how the frames of a segment are shared: how
+/// many pages are mapped how many times, then the pages with each flag.
+fn sharing_rows(sharing: &Sharing) -> Vec<Row<'static>> {
+    let total: usize = sharing.map_counts.values().sum();
+    let pages = |count: usize| format!("{} ({}%)", count, count * 100 / total.max(1));
+    let note = match sharing.truncated {
+        true => "first pages",
+        false => "",
+    };
+    let mut rows = vec![Row::new([
+        Span::raw("page sharing").bold(),
+        Span::raw(note),
+    ])];
+    for (label, count) in sharing.histogram() {
+        rows.push(Row::new([format!("mapped {}x", label), pages(count)]));
+    }
+    for (flag, count) in [
+        ("thp", sharing.thp),
+        ("ksm", sharing.ksm),
+        ("dirty", sharing.dirty),
+        ("lru active", sharing.active),
+    ] {
+        rows.push(Row::new([flag.to_string(), pages(count)]));
+    }
+    rows
+}
+
+/// The memory a cgroup is charged for, next to what its smaps add up to.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CgroupWidget {}
+
+impl CgroupWidget {
+    fn render_cgroup_widget(
+        self,
+        layout: Rect,
+        frame: &mut Frame,
+        stats: &CgroupStats,
+        memory_maps: &MemoryMapMatrix,
+    ) {
+        let pss = memory_maps
+            .iter()
+            .find(|group| group[0].pathname == MMapPath::Rollup)
+            .map(|group| Metric::Pss.sum(group))
+            .unwrap_or(0);
+        let mut rows = vec![
+            Row::new(["processes".to_string(), stats.processes.to_string()]),
+            Row::new(["smaps pss".to_string(), format_size(pss, DECIMAL)]),
+        ];
+        if let Some(current) = stats.memory_current {
+            rows.push(Row::new([
+                "memory.current".to_string(),
+                format_size(current, DECIMAL),
+            ]));
+            if current > 0 {
+                rows.push(Row::new([
+                    "pss / current".to_string(),
+                    format!("{:.0}%", pss as f64 * 100.0 / current as f64),
+                ]));
+            }
+        }
+        for (key, value) in stats.memory_stat.iter() {
+            rows.push(Row::new([key.clone(), format_size(*value, DECIMAL)]));
+        }
+        let widths = vec![Constraint::Percentage(50); 2];
+        let widget = Table::new(rows, widths).block(
+            Block::bordered()
+                .title("Cgroup")
+                .title_alignment(Alignment::Center),
+        );
+        frame.render_widget(widget, layout);
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct LogWidget {}
 
@@ -264,11 +432,26 @@ impl Widget for LogWidget {
 
 pub struct PathListWidget {
     memory_maps: Rc<MemoryMapMatrix>,
-    pub state: ListState,
+    diff: Option<Rc<SnapshotDiff>>,
+    sort: Option<Metric>,
+    /// Index into `memory_maps` of the selected group. It is kept among
+    /// the rows whenever they change with the filter, see
+    /// [`PathListWidget::reconcile`].
+    selected: Option<usize>,
+    state: ListState,
     pub toggle: bool,
-    pub searcher: Nucleo<(u64, String)>,
+    pub searcher: Nucleo<(usize, u64, String)>,
     filter: String,
+    /// Set while the filter is a query, only its matching groups are fed
+    /// to the searcher then.
+    query: Option<Query>,
+    filter_error: Option<String>,
     active_pane: bool,
+    /// Staleness of each group in `memory_maps`.
+    stale: Vec<Option<Staleness>>,
+    /// The threads whose stacks each group holds, see
+    /// [`crate::threads::stack_labels`].
+    thread_stacks: Vec<Option<String>>,
 }
 
 impl PathListWidget {
@@ -277,32 +460,117 @@ impl PathListWidget {
         state.select(Some(0));
         let num_threads = Some(available_parallelism().unwrap().get());
         let mut searcher = Nucleo::new(Config::DEFAULT, Arc::new(|| {}), num_threads, 2);
-        for mm in memory_map_matrix.iter() {
-            let values = (mm[0].address.0, app::mmpath_to_string(&mm[0].pathname));
-            searcher.injector().push(values, |values, c| {
-                c[0] = Utf32String::Ascii(values.0.to_string().as_str().into());
-                c[1] = Utf32String::Ascii(values.1.to_string().as_str().into());
-            });
-        }
+        inject_paths(&searcher, &memory_map_matrix, None);
         // Immediatly tick() so we paint the ui at startup.
         searcher.tick(10);
         Self {
             memory_maps: memory_map_matrix,
+            diff: None,
+            sort: None,
+            selected: Some(0),
             state,
             searcher,
             toggle: false,
             filter: String::new(),
+            query: None,
+            filter_error: None,
             active_pane: true,
+            stale: Vec::new(),
+            thread_stacks: Vec::new(),
         }
     }
 
-    fn render_list_widget(&mut self, layout: Rect, frame: &mut Frame, filter: String) {
-        self.filter(filter);
+    fn render_list_widget(&mut self, layout: Rect, frame: &mut Frame) {
         frame.render_widget(self, layout);
     }
 
-    fn filter(&mut self, input: String) {
+    /// Apply the text of the `/` prompt. This runs before anything reads
+    /// the selection, so the other panes follow the filter right away.
+    pub fn filter(&mut self, input: String) {
+        if input == self.filter {
+            return;
+        }
         self.filter = input;
+        if !filter::is_query(&self.filter) {
+            self.filter_error = None;
+            if self.query.take().is_some() {
+                self.reload_searcher();
+            }
+        } else {
+            // Until the query parses, the last valid one stays applied.
+            let query = Query::parse(&self.filter).and_then(|query| {
+                match query.unknown_field(&self.memory_maps) {
+                    Some(field) => Err(format!("unknown field '{}'", field).into()),
+                    None => Ok(query),
+                }
+            });
+            match query {
+                Ok(query) => {
+                    self.query = Some(query);
+                    self.filter_error = None;
+                    self.reload_searcher();
+                }
+                Err(e) => self.filter_error = Some(e.to_string()),
+            }
+        }
+        // Queries are matched when the groups are fed to the searcher.
+        let pattern = match filter::is_query(&self.filter) {
+            true => "",
+            false => &self.filter,
+        };
+        self.searcher.pattern.reparse(
+            1,
+            pattern,
+            CaseMatching::Ignore,
+            Normalization::Never,
+            false,
+        );
+        self.tick();
+    }
+
+    /// Let the searcher catch up with the filter and the groups fed to it.
+    pub fn tick(&mut self) {
+        let status = self.searcher.tick(10);
+        if status.changed && !status.running {
+            self.reconcile();
+        }
+    }
+
+    /// Keep the selected group highlighted wherever the filter moved it,
+    /// or fall back to the first row once it is filtered out.
+    fn reconcile(&mut self) {
+        let rows = self.rows();
+        match self
+            .selected
+            .and_then(|idx| rows.iter().position(|v| *v == idx))
+        {
+            Some(row) => self.state.select(Some(row)),
+            None => {
+                self.selected = rows.first().copied();
+                self.state.select(self.selected.map(|_| 0));
+            }
+        }
+    }
+
+    /// Drop the filter so every group is listed again, e.g. to jump to one.
+    pub fn clear_filter(&mut self) {
+        self.filter.clear();
+        self.query = None;
+        self.filter_error = None;
+        self.searcher
+            .pattern
+            .reparse(1, "", CaseMatching::Ignore, Normalization::Never, false);
+        self.reload_searcher();
+    }
+
+    pub fn filter_error(&self) -> Option<String> {
+        self.filter_error.clone()
+    }
+
+    fn reload_searcher(&mut self) {
+        self.searcher.restart(true);
+        inject_paths(&self.searcher, &self.memory_maps, self.query.as_ref());
+        self.tick();
     }
 
     pub fn active_pane(&mut self, active: bool) {
@@ -314,64 +582,175 @@ impl PathListWidget {
     }
 
     pub fn go_top(&mut self) {
-        self.state.select_first();
+        self.select_row(0);
     }
 
     pub fn go_bottom(&mut self) {
-        let idx = self.memory_maps.len() - 1;
-        self.state.select(Some(idx));
+        let len = self.rows().len();
+        self.select_row(len.saturating_sub(1));
     }
 
     pub fn next(&mut self) {
-        if let Some(v) = self.state.selected() {
-            let idx = (v + 1) % self.memory_maps.len();
-            self.state.select(Some(idx));
+        let len = self.rows().len();
+        if let (Some(v), true) = (self.state.selected(), len > 0) {
+            self.select_row((v + 1) % len);
         };
     }
 
     pub fn previous(&mut self) {
-        if let Some(v) = self.state.selected() {
-            let idx = if v == 0 {
-                self.memory_maps.len() - 1
-            } else {
-                v - 1
-            };
-            self.state.select(Some(idx));
+        let len = self.rows().len();
+        if let (Some(v), true) = (self.state.selected(), len > 0) {
+            let idx = if v == 0 { len - 1 } else { v - 1 };
+            self.select_row(idx);
         };
     }
 
+    /// Index into `memory_maps` of the group behind every visible row, in
+    /// the order nucleo matched them.
+    fn rows(&self) -> Vec<usize> {
+        let snapshot = self.searcher.snapshot();
+        snapshot
+            .matched_items(0..snapshot.matched_item_count())
+            .map(|item| item.data.0)
+            .collect()
+    }
+
+    fn select_row(&mut self, row: usize) {
+        if let Some(idx) = self.rows().get(row) {
+            self.selected = Some(*idx);
+            self.state.select(Some(row));
+        }
+    }
+
+    /// The selected group, none while the filter leaves no rows.
     pub fn selected_identifiers(&self) -> Option<usize> {
-        self.state.selected()
+        match self.searcher.snapshot().matched_item_count() {
+            0 => None,
+            _ => self.selected,
+        }
+    }
+
+    pub fn select_group(&mut self, idx: usize) {
+        self.selected = Some(idx);
+        let row = self.rows().iter().position(|v| *v == idx);
+        self.state.select(row);
     }
 
     pub fn selected_segments(&self) -> Option<Vec<MemoryMap>> {
         self.selected_identifiers()
             .map(|v| self.memory_maps[v].clone())
     }
+
+    pub fn set_stale(&mut self, stale: Vec<Option<Staleness>>) {
+        self.stale = stale;
+    }
+
+    pub fn set_thread_stacks(&mut self, thread_stacks: Vec<Option<String>>) {
+        self.thread_stacks = thread_stacks;
+    }
+
+    /// Swap in freshly read memory maps and re-feed the searcher. The
+    /// selection follows the group with the same path and start address,
+    /// falling back to the first group with the same path.
+    pub fn update(
+        &mut self,
+        memory_map_matrix: Rc<MemoryMapMatrix>,
+        diff: Option<Rc<SnapshotDiff>>,
+        sort: Option<Metric>,
+    ) {
+        let previous = self
+            .selected_segments()
+            .map(|mm| (app::mmpath_to_string(&mm[0].pathname), mm[0].address.0));
+        self.memory_maps = memory_map_matrix;
+        self.diff = diff;
+        self.sort = sort;
+
+        let idx = previous.and_then(|(path, start)| {
+            let same_path = |mm: &&Vec<MemoryMap>| app::mmpath_to_string(&mm[0].pathname) == path;
+            self.memory_maps
+                .iter()
+                .position(|mm| same_path(&mm) && mm[0].address.0 == start)
+                .or_else(|| self.memory_maps.iter().position(|mm| same_path(&mm)))
+        });
+        let idx = idx.unwrap_or_else(|| {
+            let last = self.memory_maps.len().saturating_sub(1);
+            self.selected.unwrap_or(0).min(last)
+        });
+        self.selected = Some(idx);
+        self.reload_searcher();
+    }
+}
+
+/// Feed the groups to the searcher. With a query, only the groups holding
+/// a matching mapping are fed, and the rollup is left out as it isn't one.
+fn inject_paths(
+    searcher: &Nucleo<(usize, u64, String)>,
+    memory_map_matrix: &MemoryMapMatrix,
+    query: Option<&Query>,
+) {
+    for (idx, mm) in memory_map_matrix.iter().enumerate() {
+        if let Some(query) = query {
+            if mm[0].pathname == MMapPath::Rollup || !mm.iter().any(|v| query.matches(v)) {
+                continue;
+            }
+        }
+        let values = (idx, mm[0].address.0, app::mmpath_to_string(&mm[0].pathname));
+        searcher.injector().push(values, |values, c| {
+            c[0] = Utf32String::Ascii(values.1.to_string().as_str().into());
+            c[1] = Utf32String::Ascii(values.2.to_string().as_str().into());
+        });
+    }
 }
 
 impl Widget for &mut PathListWidget {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let mut paths = Vec::new();
-        self.searcher.pattern.reparse(
-            1,
-            &self.filter,
-            CaseMatching::Ignore,
-            Normalization::Never,
-            false,
-        );
         for item in self
             .searcher
             .snapshot()
             .matched_items(0..self.searcher.snapshot().matched_item_count())
         {
-            let path_item = ListItem::new(format!("{:#x}  {}", item.data.0, item.data.1));
+            let (idx, address, path) = item.data;
+            let mut text = format!("{:#x}  {}", address, path);
+            if let Some(metric) = self.sort {
+                let value = metric.format(metric.sum(&self.memory_maps[*idx]));
+                text = format!("{:>10}  {}", value, text);
+            }
+            if let Some(Some(stacks)) = self.thread_stacks.get(*idx) {
+                text = format!("{}  ({})", text, stacks);
+            }
+            let stale = self.stale.get(*idx).copied().flatten();
+            if let Some(stale) = stale {
+                text = format!("{}  [{}]", text, stale.name());
+            }
+            let path_item = match &self.diff {
+                Some(diff) => {
+                    let delta = diff.groups[*idx];
+                    ListItem::new(format!(
+                        "{} {}  rss {} pss {} swap {}",
+                        delta.status.symbol(),
+                        text,
+                        format_delta(delta.rss),
+                        format_delta(delta.pss),
+                        format_delta(delta.swap),
+                    ))
+                    .style(diff_status_color(delta.status))
+                }
+                None if stale.is_some() => {
+                    ListItem::new(text).style(Style::default().fg(Color::Red))
+                }
+                None => ListItem::new(text),
+            };
             paths.push(path_item.clone());
         }
 
+        let title = match self.sort {
+            Some(metric) => format!("Path (by {})", metric.name()),
+            None => "Path".to_string(),
+        };
         let inner_block = Block::bordered()
             .border_style(selected_pane_color(&self.active_pane))
-            .title("Path")
+            .title(title)
             .title_alignment(Alignment::Center);
 
         let list = List::new(paths)
@@ -382,6 +761,277 @@ impl Widget for &mut PathListWidget {
     }
 }
 
+/// A node of the tree pane before it is turned into a [`TreeItem`].
+#[derive(Default)]
+struct TreeNode {
+    children: Vec<(String, TreeNode)>,
+    /// Group and segment index of every mapping below this node.
+    segments: Vec<(usize, usize)>,
+}
+
+pub struct TreeWidget {
+    items: Vec<TreeItem<'static, String>>,
+    /// The first mapping below every node, so the other panes can follow
+    /// the tree selection.
+    first_segments: HashMap<Vec<String>, (usize, usize)>,
+    state: TreeState<String>,
+    active_pane: bool,
+}
+
+impl TreeWidget {
+    pub fn new(memory_map_matrix: &MemoryMapMatrix) -> Self {
+        let mut widget = Self {
+            items: Vec::new(),
+            first_segments: HashMap::new(),
+            state: TreeState::default(),
+            active_pane: false,
+        };
+        widget.update(memory_map_matrix);
+        // Start with the categories opened, they are few.
+        for item in widget.items.iter() {
+            widget.state.open(vec![item.identifier().clone()]);
+        }
+        widget.state.select_first();
+        widget
+    }
+
+    fn render_tree_widget(&mut self, layout: Rect, frame: &mut Frame) {
+        frame.render_widget(self, layout);
+    }
+
+    pub fn active_pane(&mut self, active: bool) {
+        self.active_pane = active;
+    }
+
+    pub fn next(&mut self) {
+        self.state.key_down();
+    }
+
+    pub fn previous(&mut self) {
+        self.state.key_up();
+    }
+
+    pub fn go_top(&mut self) {
+        self.state.select_first();
+    }
+
+    pub fn go_bottom(&mut self) {
+        self.state.select_last();
+    }
+
+    pub fn open(&mut self) {
+        self.state.key_right();
+    }
+
+    pub fn close(&mut self) {
+        self.state.key_left();
+    }
+
+    pub fn toggle_selected(&mut self) {
+        self.state.toggle_selected();
+    }
+
+    /// Group and segment index of the first mapping below the selected node.
+    pub fn selected_segment(&self) -> Option<(usize, usize)> {
+        self.first_segments
+            .get(&self.state.selected().to_vec())
+            .copied()
+    }
+
+    /// Select the leaf of a mapping, opening the nodes above it.
+    pub fn select_segment(&mut self, group: usize, segment: usize) {
+        let leaf = self
+            .first_segments
+            .iter()
+            .filter(|(_, first)| **first == (group, segment))
+            .map(|(identifier, _)| identifier)
+            .max_by_key(|identifier| identifier.len());
+        if let Some(leaf) = leaf.cloned() {
+            for depth in 1..leaf.len() {
+                self.state.open(leaf[..depth].to_vec());
+            }
+            self.state.select(leaf);
+        }
+    }
+
+    /// Rebuild the tree from freshly read memory maps. Opened nodes and the
+    /// selection are tracked by identifier, so they survive as long as the
+    /// node is still there.
+    pub fn update(&mut self, memory_map_matrix: &MemoryMapMatrix) {
+        let root = tree_nodes(memory_map_matrix);
+        self.first_segments.clear();
+        match tree_items(&root, &[], memory_map_matrix, &mut self.first_segments) {
+            Ok(items) => self.items = items,
+            Err(e) => warn!(target:"TreeWidget", "Failed to build tree: {}", e),
+        }
+    }
+}
+
+/// Mappings go under their category and, if file-backed, their directory,
+/// then under their path. Categories other than file-backed that only hold
+/// one path (e.g. heap) take the segments directly.
+fn tree_nodes(memory_map_matrix: &MemoryMapMatrix) -> TreeNode {
+    let mut category_paths: HashMap<Category, Vec<String>> = HashMap::new();
+    for mm in memory_map_matrix.iter().flatten() {
+        let paths = category_paths
+            .entry(Category::of(&mm.pathname))
+            .or_default();
+        let path = app::mmpath_to_string(&mm.pathname);
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+
+    let mut root = TreeNode::default();
+    for (group_idx, group) in memory_map_matrix.iter().enumerate() {
+        for (segment_idx, mm) in group.iter().enumerate() {
+            if mm.pathname == MMapPath::Rollup {
+                continue;
+            }
+            let category = Category::of(&mm.pathname);
+            let path = app::mmpath_to_string(&mm.pathname);
+            let mut keys = vec![category.name().to_string()];
+            if let MMapPath::Path(file) = &mm.pathname {
+                let directory = file.parent().map(|v| v.to_string_lossy().into_owned());
+                keys.push(directory.unwrap_or_default());
+                keys.push(path);
+            } else if category_paths[&category].len() > 1 {
+                keys.push(path);
+            }
+
+            let entry = (group_idx, segment_idx);
+            let mut node = &mut root;
+            node.segments.push(entry);
+            for key in keys {
+                let idx = match node.children.iter().position(|(k, _)| *k == key) {
+                    Some(idx) => idx,
+                    None => {
+                        node.children.push((key, TreeNode::default()));
+                        node.children.len() - 1
+                    }
+                };
+                node = &mut node.children[idx].1;
+                node.segments.push(entry);
+            }
+            // Segments are unique, so the leaves are pushed without a lookup.
+            let leaf = format!("{:#x}-{:#x}", mm.address.0, mm.address.1);
+            node.children.push((
+                leaf,
+                TreeNode {
+                    children: Vec::new(),
+                    segments: vec![entry],
+                },
+            ));
+        }
+    }
+    root
+}
+
+fn tree_items(
+    node: &TreeNode,
+    identifier: &[String],
+    memory_map_matrix: &MemoryMapMatrix,
+    first_segments: &mut HashMap<Vec<String>, (usize, usize)>,
+) -> io::Result<Vec<TreeItem<'static, String>>> {
+    let sum = |segments: &[(usize, usize)], key: &str| -> u64 {
+        segments
+            .iter()
+            .map(|(g, s)| {
+                *memory_map_matrix[*g][*s]
+                    .extension
+                    .map
+                    .get(key)
+                    .unwrap_or(&0)
+            })
+            .sum()
+    };
+    // Leaves stay in address order, everything above is ranked by Pss.
+    let mut children: Vec<&(String, TreeNode)> = node.children.iter().collect();
+    children.sort_by_key(|(_, child)| match child.children.is_empty() {
+        true => Reverse(0),
+        false => Reverse(sum(&child.segments, "Pss")),
+    });
+
+    let mut items = Vec::new();
+    for (key, child) in children {
+        let child_identifier = [identifier, std::slice::from_ref(key)].concat();
+        first_segments.insert(child_identifier.clone(), child.segments[0]);
+        // Files are listed under their directory, no need to repeat it.
+        let parent = identifier.last().map_or("", |v| v.as_str());
+        let mut name = match key.strip_prefix(parent) {
+            Some(file) if !parent.is_empty() => file.trim_start_matches('/').to_string(),
+            _ => key.clone(),
+        };
+        if child.children.is_empty() {
+            let (g, s) = child.segments[0];
+            name = format!("{} {}", key, memory_map_matrix[g][s].perms.as_str());
+        }
+        let text = format!(
+            "{}  rss {} pss {}",
+            name,
+            format_size(sum(&child.segments, "Rss"), DECIMAL),
+            format_size(sum(&child.segments, "Pss"), DECIMAL),
+        );
+        let item = match child.children.is_empty() {
+            true => TreeItem::new_leaf(key.clone(), text),
+            false => TreeItem::new(
+                key.clone(),
+                text,
+                tree_items(child, &child_identifier, memory_map_matrix, first_segments)?,
+            )?,
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+impl Widget for &mut TreeWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered()
+            .border_style(selected_pane_color(&self.active_pane))
+            .title("Tree")
+            .title_alignment(Alignment::Center);
+        match Tree::new(&self.items) {
+            Ok(tree) => {
+                let tree = tree
+                    .block(block)
+                    .highlight_style(Style::new().light_yellow());
+                StatefulWidget::render(tree, area, buf, &mut self.state)
+            }
+            Err(e) => Paragraph::new(e.to_string()).block(block).render(area, buf),
+        }
+    }
+}
+
+/// The keys of the legend and what they do, in the order shown. Help and
+/// quit stay last.
+const LEGEND_KEYS: [(&str, &str); 21] = [
+    ("tab", "pane"),
+    ("j/k", "move"),
+    ("g/G", "top/bottom"),
+    ("/", "filter"),
+    (":", "goto"),
+    ("r", "refresh"),
+    ("b", "baseline"),
+    ("s", "sort"),
+    ("m", "merge"),
+    ("t", "tree"),
+    ("a", "layout"),
+    ("T", "treemap"),
+    ("R", "pages"),
+    ("p", "open process"),
+    ("1-9", "tab"),
+    ("x", "close tab"),
+    ("c", "compare tabs"),
+    ("w", "who maps"),
+    ("H", "threads"),
+    ("h", "help"),
+    ("ESC/q", "quit"),
+];
+
+/// The keys of the legend while the help is open.
+const HELP_LEGEND_KEYS: [(&str, &str); 2] = [("v", "vm flags"), ("h", "exit")];
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct LegendWidget {
     help_toggled: bool,
@@ -395,34 +1045,90 @@ impl LegendWidget {
     pub fn help_toggled(&mut self) {
         self.help_toggled = !self.help_toggled;
     }
+
+    /// Rows taken at `width`, the border and every line of keys.
+    fn height(&self, width: u16) -> u16 {
+        self.lines(width).len() as u16 + 1
+    }
+
+    /// Keys that don't fit the width wrap onto the next line.
+    fn lines(&self, width: u16) -> Vec<String> {
+        let keys = match self.help_toggled {
+            true => HELP_LEGEND_KEYS.as_slice(),
+            false => LEGEND_KEYS.as_slice(),
+        };
+        let mut lines: Vec<String> = vec![String::new()];
+        for (key, action) in keys {
+            let key = format!("{} - {}", key, action);
+            let line = lines.last_mut().unwrap();
+            if !line.is_empty() && line.chars().count() + key.chars().count() + 3 > width as usize {
+                lines.push(key);
+            } else {
+                if !line.is_empty() {
+                    line.push_str("   ");
+                }
+                line.push_str(&key);
+            }
+        }
+        lines
+    }
 }
 
 impl Widget for LegendWidget {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let rows: Vec<Row> = if self.help_toggled {
-            vec![Row::new(vec![
-                Cell::from(Text::from("v - vm flags").alignment(Alignment::Center)),
-                Cell::from(Text::from("h - exit").alignment(Alignment::Center)),
-            ])]
+        let lines = self.lines(area.width);
+        let widget = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<Line>>())
+            .alignment(Alignment::Center)
+            .block(
+                Block::new()
+                    .borders(Borders::TOP)
+                    .border_type(BorderType::Double),
+            );
+        Clear.render(area, buf);
+        Widget::render(widget, area, buf);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimelineWidget {
+    position: usize,
+    len: usize,
+    elapsed: u64,
+}
+
+impl TimelineWidget {
+    fn render_timeline_widget(&mut self, layout: Rect, frame: &mut Frame, timeline: &Timeline) {
+        self.position = timeline.position();
+        self.len = timeline.len();
+        self.elapsed = timeline.elapsed();
+        frame.render_widget(*self, layout);
+    }
+}
+
+impl Widget for TimelineWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let ratio = if self.len > 1 {
+            self.position as f64 / (self.len - 1) as f64
         } else {
-            vec![Row::new(vec![
-                Cell::from(Text::from("tab - switch pane").alignment(Alignment::Center)),
-                Cell::from(Text::from("j - down").alignment(Alignment::Center)),
-                Cell::from(Text::from("k - up").alignment(Alignment::Center)),
-                Cell::from(Text::from("g - top").alignment(Alignment::Center)),
-                Cell::from(Text::from("G - bottom").alignment(Alignment::Center)),
-                Cell::from(Text::from("/ - filter path").alignment(Alignment::Center)),
-                Cell::from(Text::from("h - help").alignment(Alignment::Center)),
-                Cell::from(Text::from("ESC/q - quit").alignment(Alignment::Center)),
-            ])]
+            1.0
         };
-        let widths = [Constraint::Length(20); 8];
-        let widget = Table::new(rows, widths).block(
-            Block::new()
-                .borders(Borders::TOP)
-                .border_type(BorderType::Double),
+        let label = format!(
+            "{}/{}  +{:02}:{:02}:{:02}",
+            self.position + 1,
+            self.len,
+            self.elapsed / 3600,
+            self.elapsed / 60 % 60,
+            self.elapsed % 60
         );
-        Clear.render(area, buf);
+        let widget = LineGauge::default()
+            .block(
+                Block::bordered()
+                    .title("Timeline ([ ] - step, { } - first/last)")
+                    .title_alignment(Alignment::Center),
+            )
+            .filled_style(Style::new().light_yellow())
+            .label(label)
+            .ratio(ratio);
         Widget::render(widget, area, buf);
     }
 }
@@ -430,11 +1136,18 @@ impl Widget for LegendWidget {
 #[derive(Clone, Debug, Default)]
 pub struct PathFilterWidget {
     pub filter: String,
+    error: Option<String>,
 }
 
 impl PathFilterWidget {
-    fn render_path_filter_widget(&self, layout: Rect, frame: &mut Frame) {
-        frame.render_widget(self, layout);
+    fn render_path_filter_widget(
+        &mut self,
+        layout: Rect,
+        frame: &mut Frame,
+        error: Option<String>,
+    ) {
+        self.error = error;
+        frame.render_widget(&*self, layout);
     }
 }
 
@@ -445,36 +1158,1337 @@ impl Widget for &PathFilterWidget {
             .constraints([Constraint::Fill(1)])
             .split(area);
         let term_block = Block::default()
-            .title("Search for Path")
+            .title("Search for Path (or query, e.g. rss > 10M && perms ~ \"x\" && path ~ libc)")
             .borders(Borders::ALL);
-        let term_text = Paragraph::new(self.filter.clone()).block(term_block);
+        let mut lines = vec![Line::from(self.filter.clone())];
+        if let Some(error) = &self.error {
+            lines.push(Line::styled(error.clone(), Style::default().fg(Color::Red)));
+        }
+        let term_text = Paragraph::new(lines).block(term_block);
         // Important to Clear before painting a new widget on top of existing layout.
         Clear.render(area, buf);
         Widget::render(term_text, popup_chunks[0], buf);
     }
 }
 
-#[derive(Default)]
-pub struct HelpWidget {
-    toggle: bool,
-    toggle_vm_flags: bool,
+/// The whole virtual address space as a strip of cells, one color per
+/// [`Category`] and shaded by how much of each mapping is resident.
+pub struct AddressSpaceWidget {
+    memory_maps: Rc<MemoryMapMatrix>,
+    /// Group and segment index of every mapping, in address order.
+    order: Vec<(usize, usize)>,
+    /// Position in `order` of the segment selected in the segment table.
+    selected: Option<usize>,
+    active_pane: bool,
 }
 
-impl HelpWidget {
-    fn render_help_widget(&self, layout: Rect, frame: &mut Frame) {
-        frame.render_widget(self, layout)
+impl AddressSpaceWidget {
+    pub fn new(memory_map_matrix: Rc<MemoryMapMatrix>) -> Self {
+        let mut widget = Self {
+            memory_maps: Rc::clone(&memory_map_matrix),
+            order: Vec::new(),
+            selected: None,
+            active_pane: false,
+        };
+        widget.update(memory_map_matrix);
+        widget
     }
 
-    pub fn toggle(&mut self) {
-        // If HelpWidget is being disabled, then also disable vm_flags to avoid confusion.
-        if self.toggle {
-            self.toggle_vm_flags = false;
-        }
-        self.toggle = !self.toggle;
+    fn render_address_space_widget(
+        &mut self,
+        layout: Rect,
+        frame: &mut Frame,
+        selected: (usize, usize),
+    ) {
+        self.selected = self.order.iter().position(|v| *v == selected);
+        frame.render_widget(self, layout);
     }
 
-    pub fn toggle_vm_flags(&mut self) {
-        // If HelpWidget is not active, do not activate vm_flags screen to avoid confusion.
+    pub fn active_pane(&mut self, active: bool) {
+        self.active_pane = active;
+    }
+
+    pub fn next(&mut self) {
+        let last = self.order.len().saturating_sub(1);
+        self.selected = Some(self.selected.map_or(0, |v| (v + 1).min(last)));
+    }
+
+    pub fn previous(&mut self) {
+        self.selected = Some(self.selected.map_or(0, |v| v.saturating_sub(1)));
+    }
+
+    pub fn go_top(&mut self) {
+        self.selected = Some(0);
+    }
+
+    pub fn go_bottom(&mut self) {
+        self.selected = Some(self.order.len().saturating_sub(1));
+    }
+
+    pub fn selected_mapping(&self) -> Option<(usize, usize)> {
+        self.selected.and_then(|v| self.order.get(v).copied())
+    }
+
+    pub fn update(&mut self, memory_map_matrix: Rc<MemoryMapMatrix>) {
+        let mut order: Vec<(usize, usize)> = Vec::new();
+        for (g, group) in memory_map_matrix.iter().enumerate() {
+            for (s, mm) in group.iter().enumerate() {
+                if mm.pathname != MMapPath::Rollup {
+                    order.push((g, s));
+                }
+            }
+        }
+        order.sort_by_key(|(g, s)| memory_map_matrix[*g][*s].address);
+        self.order = order;
+        self.memory_maps = memory_map_matrix;
+    }
+
+    /// Number of cells given to every mapping, and to the hole in front of
+    /// it. Both are scaled by the log of their size and holes by half as
+    /// much again, so a 128TiB hole takes about as much room as a few large
+    /// mappings. As long as there are enough cells every mapping and hole
+    /// gets at least one, and the selected mapping always does.
+    fn cells(&self, total: usize) -> Vec<(usize, usize)> {
+        let weight = |bytes: u64| (bytes / 4096 + 1).ilog2() as usize + 1;
+        let mut weights = Vec::new();
+        let mut end = None;
+        for (g, s) in self.order.iter() {
+            let mm = &self.memory_maps[*g][*s];
+            let hole = match end {
+                Some(end) if mm.address.0 > end => weight(mm.address.0 - end).div_ceil(2),
+                _ => 0,
+            };
+            weights.push((hole, weight(mm.address.1 - mm.address.0)));
+            end = Some(mm.address.1);
+        }
+        let items = weights
+            .iter()
+            .map(|(h, _)| (*h > 0) as usize)
+            .sum::<usize>()
+            + weights.len();
+        let (minimum, spare) = match items <= total {
+            true => (1, total - items),
+            false => (0, total),
+        };
+        let sum: usize = weights.iter().map(|(h, m)| h + m).sum::<usize>().max(1);
+        let mut cells: Vec<(usize, usize)> = weights
+            .iter()
+            .map(|(h, m)| {
+                let hole = match h {
+                    0 => 0,
+                    h => minimum + h * spare / sum,
+                };
+                (hole, minimum + m * spare / sum)
+            })
+            .collect();
+        // Otherwise the selection could be rounded away. Its cell comes out
+        // of the rounding if any is left, else from the largest mapping or
+        // hole, so the cells never add up to more than `total`.
+        if let Some(selected) = self
+            .selected
+            .filter(|idx| cells.get(*idx).is_some_and(|(_, mapping)| *mapping == 0))
+        {
+            let used: usize = cells.iter().map(|(h, m)| h + m).sum();
+            if used >= total {
+                let largest = cells
+                    .iter_mut()
+                    .flat_map(|(hole, mapping)| [hole, mapping])
+                    .max_by_key(|cell| **cell);
+                match largest {
+                    Some(cell) if *cell > 0 => *cell -= 1,
+                    _ => return cells,
+                }
+            }
+            cells[selected].1 = 1;
+        }
+        cells
+    }
+}
+
+fn category_color(category: Category) -> Color {
+    match category {
+        Category::FileBacked => Color::Blue,
+        Category::Anonymous => Color::Green,
+        Category::Heap => Color::Magenta,
+        Category::Stack => Color::Cyan,
+        Category::Special => Color::Gray,
+    }
+}
+
+impl Widget for &mut AddressSpaceWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut title = vec![Span::raw("Address space (")];
+        for category in [
+            Category::FileBacked,
+            Category::Anonymous,
+            Category::Heap,
+            Category::Stack,
+            Category::Special,
+        ] {
+            title.push(Span::styled(
+                format!("{} ", category.name()),
+                Style::default().fg(category_color(category)),
+            ));
+        }
+        title.push(Span::raw("by █▓▒░ residency, holes log scaled)"));
+        let mut block = Block::bordered()
+            .border_style(selected_pane_color(&self.active_pane))
+            .title(Line::from(title))
+            .title_alignment(Alignment::Center);
+        if let Some((g, s)) = self.selected_mapping() {
+            block = block.title_bottom(lookup::describe(&self.memory_maps[g][s]));
+        }
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        // The strip wraps over all rows of the pane, left to right.
+        let width = inner.width as usize;
+        let total = width * inner.height as usize;
+        let mut cell = 0;
+        for (idx, (hole, cells)) in self.cells(total).into_iter().enumerate() {
+            let (g, s) = self.order[idx];
+            let mm = &self.memory_maps[g][s];
+            let size = mm.address.1 - mm.address.0;
+            let rss = *mm.extension.map.get("Rss").unwrap_or(&0);
+            let symbol = match rss * 4 / size.max(1) {
+                0 if rss == 0 => "·",
+                0 => "░",
+                1 => "▒",
+                2 => "▓",
+                _ => "█",
+            };
+            let mut style = Style::default().fg(category_color(Category::of(&mm.pathname)));
+            if self.selected == Some(idx) {
+                style = style.bg(Color::LightYellow);
+            }
+            cell += hole;
+            for _ in 0..cells {
+                if cell >= total {
+                    break;
+                }
+                let x = inner.x + (cell % width) as u16;
+                let y = inner.y + (cell / width) as u16;
+                buf.set_string(x, y, symbol, style);
+                cell += 1;
+            }
+        }
+    }
+}
+
+/// A page table bit: its name, how to read it and its color.
+type PageBit = (&'static str, fn(&Page) -> bool, Color);
+
+/// The bits the [`PagemapWidget`] draws a heatmap of.
+const PAGE_BITS: [PageBit; 4] = [
+    ("present", |page| page.present, Color::Green),
+    ("swapped", |page| page.swapped, Color::Magenta),
+    ("file/shared", |page| page.file_or_shared, Color::Blue),
+    ("soft-dirty", |page| page.soft_dirty, Color::Yellow),
+];
+
+/// The pages of the selected segment, one heatmap per page table bit.
+#[derive(Default)]
+pub struct PagemapWidget {
+    /// The pid and address range `residency` was read for.
+    read_for: Option<(i32, u64, u64)>,
+    residency: Option<Result<Residency, String>>,
+    /// How the frames of the pages are shared, shown in the info pane.
+    sharing: Option<Result<Sharing, String>>,
+}
+
+impl PagemapWidget {
+    fn render_pagemap_widget(&self, layout: Rect, frame: &mut Frame) {
+        frame.render_widget(self, layout);
+    }
+
+    /// Read the pages of `segment` and how their frames are shared, unless
+    /// they were read already.
+    fn update(&mut self, pid: Option<i32>, segment: Option<MemoryMap>) {
+        let Some(pid) = pid else {
+            self.read_for = None;
+            self.residency = Some(Err("pagemap is only read from a running process".into()));
+            self.sharing = None;
+            return;
+        };
+        let Some(segment) = segment else {
+            self.read_for = None;
+            self.residency = None;
+            self.sharing = None;
+            return;
+        };
+        // The rollup spans the whole address space.
+        if segment.pathname == MMapPath::Rollup {
+            self.read_for = None;
+            self.residency = Some(Err("the rollup has no pages of its own".into()));
+            self.sharing = None;
+            return;
+        }
+        let key = (pid, segment.address.0, segment.address.1);
+        if self.read_for == Some(key) {
+            return;
+        }
+        self.read_for = Some(key);
+        self.residency = Some(
+            Residency::read(pid, segment.address.0, segment.address.1).map_err(|e| e.to_string()),
+        );
+        self.sharing = match &self.residency {
+            Some(Ok(residency)) => Some(Sharing::read(residency).map_err(|e| e.to_string())),
+            _ => None,
+        };
+    }
+
+    /// Read the pages again on the next render, e.g. after a refresh.
+    pub fn invalidate(&mut self) {
+        self.read_for = None;
+    }
+}
+
+impl Widget for &PagemapWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered()
+            .title("Pages (by █▓▒░ share of pages with the bit)")
+            .title_alignment(Alignment::Center);
+        let residency = match &self.residency {
+            Some(Ok(residency)) => residency,
+            Some(Err(e)) => {
+                Paragraph::new(e.clone())
+                    .alignment(Alignment::Center)
+                    .block(block)
+                    .render(area, buf);
+                return;
+            }
+            None => {
+                Paragraph::new("no segment")
+                    .alignment(Alignment::Center)
+                    .block(block)
+                    .render(area, buf);
+                return;
+            }
+        };
+        let total = residency.pages.len();
+        let percent = |count: usize| count * 100 / total.max(1);
+        let mut notes = vec![format!(
+            "{:#x}-{:#x}  {} pages",
+            residency.start, residency.end, total
+        )];
+        if residency.truncated() {
+            notes.push("first pages only".to_string());
+        }
+        if residency.pfns_hidden() {
+            notes.push("page frames hidden without CAP_SYS_ADMIN".to_string());
+        }
+        let block = block.title_bottom(Line::from(notes.join("   ")).centered());
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let label_width = 18;
+        let rows = (inner.height / PAGE_BITS.len() as u16).max(1);
+        let width = inner.width.saturating_sub(label_width) as usize;
+        // A cell stands for several pages once there are more pages than
+        // cells, its symbol tells how many of them have the bit.
+        let cells = (width * rows as usize).min(total);
+        for (idx, (name, bit, color)) in PAGE_BITS.iter().enumerate() {
+            let y = inner.y + idx as u16 * rows;
+            if y >= inner.y + inner.height {
+                break;
+            }
+            let count = residency.count(*bit);
+            buf.set_string(
+                inner.x,
+                y,
+                format!("{:<11}{:>4}%", name, percent(count)),
+                Style::default().fg(*color),
+            );
+            for cell in 0..cells {
+                let pages = &residency.pages[cell * total / cells..(cell + 1) * total / cells];
+                let set = pages.iter().filter(|page| bit(page)).count();
+                let symbol = match set * 4 / pages.len().max(1) {
+                    0 if set == 0 => "·",
+                    0 => "░",
+                    1 => "▒",
+                    2 => "▓",
+                    _ => "█",
+                };
+                let x = inner.x + label_width + (cell % width) as u16;
+                let y = y + (cell / width) as u16;
+                buf.set_string(x, y, symbol, Style::default().fg(*color));
+            }
+        }
+    }
+}
+
+/// Path groups as rectangles sized by a metric, nested by [`Category`].
+pub struct TreemapWidget {
+    memory_maps: Rc<MemoryMapMatrix>,
+    metric: Metric,
+    /// Group indexes and values per category, largest first.
+    tiles: Vec<(Category, Vec<(usize, u64)>)>,
+    /// Position of the selected group when walking the tiles in order.
+    selected: Option<usize>,
+    active_pane: bool,
+}
+
+impl TreemapWidget {
+    pub fn new(memory_map_matrix: Rc<MemoryMapMatrix>, metric: Metric) -> Self {
+        let mut widget = Self {
+            memory_maps: Rc::clone(&memory_map_matrix),
+            metric,
+            tiles: Vec::new(),
+            selected: None,
+            active_pane: false,
+        };
+        widget.update(memory_map_matrix, metric);
+        widget
+    }
+
+    fn render_treemap_widget(
+        &mut self,
+        layout: Rect,
+        frame: &mut Frame,
+        selected_group: Option<usize>,
+    ) {
+        self.selected = selected_group.and_then(|group| self.groups().position(|v| v == group));
+        frame.render_widget(self, layout);
+    }
+
+    pub fn active_pane(&mut self, active: bool) {
+        self.active_pane = active;
+    }
+
+    fn groups(&self) -> impl Iterator<Item = usize> + '_ {
+        self.tiles
+            .iter()
+            .flat_map(|(_, groups)| groups.iter().map(|(group, _)| *group))
+    }
+
+    pub fn next(&mut self) {
+        let last = self.groups().count().saturating_sub(1);
+        self.selected = Some(self.selected.map_or(0, |v| (v + 1).min(last)));
+    }
+
+    pub fn previous(&mut self) {
+        self.selected = Some(self.selected.map_or(0, |v| v.saturating_sub(1)));
+    }
+
+    pub fn go_top(&mut self) {
+        self.selected = Some(0);
+    }
+
+    pub fn go_bottom(&mut self) {
+        self.selected = Some(self.groups().count().saturating_sub(1));
+    }
+
+    pub fn selected_group(&self) -> Option<usize> {
+        self.selected.and_then(|v| self.groups().nth(v))
+    }
+
+    /// Groups worth nothing in `metric` have no area and are left out, as is
+    /// the rollup.
+    pub fn update(&mut self, memory_map_matrix: Rc<MemoryMapMatrix>, metric: Metric) {
+        let mut tiles: Vec<(Category, Vec<(usize, u64)>)> = Vec::new();
+        for (idx, group) in memory_map_matrix.iter().enumerate() {
+            let value = metric.sum(group);
+            if group[0].pathname == MMapPath::Rollup || value == 0 {
+                continue;
+            }
+            let category = Category::of(&group[0].pathname);
+            match tiles.iter_mut().find(|(c, _)| *c == category) {
+                Some((_, groups)) => groups.push((idx, value)),
+                None => tiles.push((category, vec![(idx, value)])),
+            }
+        }
+        for (_, groups) in tiles.iter_mut() {
+            groups.sort_by_key(|(_, value)| Reverse(*value));
+        }
+        tiles.sort_by_key(|(_, groups)| Reverse(groups.iter().map(|(_, v)| v).sum::<u64>()));
+        self.tiles = tiles;
+        self.metric = metric;
+        self.memory_maps = memory_map_matrix;
+    }
+}
+
+impl Widget for &mut TreemapWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered()
+            .border_style(selected_pane_color(&self.active_pane))
+            .title(format!("Treemap (by {})", self.metric.name()))
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let totals: Vec<u64> = self
+            .tiles
+            .iter()
+            .map(|(_, groups)| groups.iter().map(|(_, v)| v).sum())
+            .collect();
+        let mut position = 0;
+        for ((category, groups), category_area) in self.tiles.iter().zip(squarify(&totals, inner)) {
+            let values: Vec<u64> = groups.iter().map(|(_, v)| *v).collect();
+            for ((group, value), tile) in groups.iter().zip(squarify(&values, category_area)) {
+                let style = match self.selected == Some(position) {
+                    true => Style::new().bg(Color::LightYellow).fg(Color::Black),
+                    false => Style::new().fg(category_color(*category)),
+                };
+                position += 1;
+                if tile.is_empty() {
+                    continue;
+                }
+                let name = match &self.memory_maps[*group][0].pathname {
+                    MMapPath::Path(path) => path
+                        .file_name()
+                        .map_or_else(|| path.to_string_lossy(), |v| v.to_string_lossy())
+                        .into_owned(),
+                    path => app::mmpath_to_string(path),
+                };
+                let label = format!("{} {}", name, self.metric.format(*value));
+                buf.set_style(tile, style);
+                // Only the top and left edges are drawn, the neighbours or
+                // the pane border close the tile on the other sides.
+                if tile.width > 1 && tile.height > 1 {
+                    Block::new()
+                        .borders(Borders::TOP | Borders::LEFT)
+                        .border_style(style)
+                        .title(label)
+                        .render(tile, buf);
+                } else {
+                    buf.set_stringn(tile.x, tile.y, label, tile.width as usize, style);
+                }
+            }
+        }
+    }
+}
+
+/// Squarified treemap layout (Bruls et al.) of `values`, largest first,
+/// over `area`. Cells are about twice as high as wide, so the layout runs
+/// on a canvas of double height to come out square on screen.
+fn squarify(values: &[u64], area: Rect) -> Vec<Rect> {
+    let total: u64 = values.iter().sum();
+    if total == 0 || area.is_empty() {
+        return vec![Rect::default(); values.len()];
+    }
+    let (mut x, mut y) = (area.x as f64, area.y as f64 * 2.0);
+    let (mut w, mut h) = (area.width as f64, area.height as f64 * 2.0);
+    let scale = w * h / total as f64;
+    let areas: Vec<f64> = values.iter().map(|v| *v as f64 * scale).collect();
+
+    let mut rects = Vec::new();
+    let mut i = 0;
+    while i < areas.len() {
+        // Grow the row while that makes its worst aspect ratio better.
+        let side = w.min(h);
+        let mut j = i + 1;
+        while j < areas.len() && worst(&areas[i..=j], side) <= worst(&areas[i..j], side) {
+            j += 1;
+        }
+        let sum: f64 = areas[i..j].iter().sum();
+        if w >= h {
+            let width = sum / h;
+            let mut top = y;
+            for a in areas[i..j].iter() {
+                rects.push((x, top, width, a / width));
+                top += a / width;
+            }
+            x += width;
+            w -= width;
+        } else {
+            let height = sum / w;
+            let mut left = x;
+            for a in areas[i..j].iter() {
+                rects.push((left, y, a / height, height));
+                left += a / height;
+            }
+            y += height;
+            h -= height;
+        }
+        i = j;
+    }
+
+    // Round the edges rather than the sizes, so the tiles don't overlap or
+    // leave gaps.
+    rects
+        .into_iter()
+        .map(|(x, y, w, h)| {
+            let (left, right) = (x.round(), (x + w).round());
+            let (top, bottom) = ((y / 2.0).round(), ((y + h) / 2.0).round());
+            Rect::new(
+                left as u16,
+                top as u16,
+                (right - left) as u16,
+                (bottom - top) as u16,
+            )
+        })
+        .collect()
+}
+
+fn worst(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    row.iter()
+        .map(|a| {
+            let ratio = side * side * a / (sum * sum);
+            ratio.max(1.0 / ratio)
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Which part of the comparison j/k move through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CompareFocus {
+    Paths,
+    Left,
+    Right,
+}
+
+/// Two processes side by side: their paths lined up with the differences,
+/// and the segments of the selected path in each of them below.
+pub struct CompareWidget {
+    left_name: String,
+    right_name: String,
+    /// The captures `comparison` was built from, to tell when to rebuild it.
+    left_snapshot: Rc<MemoryMapMatrix>,
+    right_snapshot: Rc<MemoryMapMatrix>,
+    comparison: Comparison,
+    state: TableState,
+    left_segments: SegmentTableWidget,
+    right_segments: SegmentTableWidget,
+    focus: CompareFocus,
+}
+
+impl CompareWidget {
+    pub fn new(
+        left_name: String,
+        left: Rc<MemoryMapMatrix>,
+        right_name: String,
+        right: Rc<MemoryMapMatrix>,
+    ) -> Self {
+        let comparison = compare::compare(&left, &right);
+        let left_segments =
+            SegmentTableWidget::new(Rc::new(comparison.left.clone())).with_title(left_name.clone());
+        let right_segments = SegmentTableWidget::new(Rc::new(comparison.right.clone()))
+            .with_title(right_name.clone());
+        let mut widget = Self {
+            left_name,
+            right_name,
+            left_snapshot: left,
+            right_snapshot: right,
+            comparison,
+            state: TableState::default().with_selected(0),
+            left_segments,
+            right_segments,
+            focus: CompareFocus::Paths,
+        };
+        widget.select_path();
+        widget.focus(CompareFocus::Paths);
+        widget
+    }
+
+    /// Compare the captures again if either process was refreshed. The
+    /// selection stays on the same path.
+    pub fn update(&mut self, left: Rc<MemoryMapMatrix>, right: Rc<MemoryMapMatrix>) {
+        if Rc::ptr_eq(&left, &self.left_snapshot) && Rc::ptr_eq(&right, &self.right_snapshot) {
+            return;
+        }
+        let previous = self.selected().map(|path| path.path.clone());
+        self.comparison = compare::compare(&left, &right);
+        self.left_snapshot = left;
+        self.right_snapshot = right;
+        let row = previous
+            .and_then(|previous| {
+                self.comparison
+                    .paths
+                    .iter()
+                    .position(|path| path.path == previous)
+            })
+            .unwrap_or(0);
+        self.state.select(Some(row));
+        self.left_segments
+            .update(Rc::new(self.comparison.left.clone()), None, None);
+        self.right_segments
+            .update(Rc::new(self.comparison.right.clone()), None, None);
+        self.select_path();
+    }
+
+    fn selected(&self) -> Option<&PathComparison> {
+        self.comparison.paths.get(self.state.selected()?)
+    }
+
+    /// Show the segments of the selected path in both tables.
+    fn select_path(&mut self) {
+        let (left, right) = match self.selected() {
+            Some(path) => (path.left, path.right),
+            None => (None, None),
+        };
+        self.left_segments.selected_identifier(left);
+        self.right_segments.selected_identifier(right);
+    }
+
+    fn focus(&mut self, focus: CompareFocus) {
+        self.focus = focus;
+        self.left_segments.active_pane(focus == CompareFocus::Left);
+        self.right_segments
+            .active_pane(focus == CompareFocus::Right);
+    }
+
+    pub fn switch_pane(&mut self) {
+        self.focus(match self.focus {
+            CompareFocus::Paths => CompareFocus::Left,
+            CompareFocus::Left => CompareFocus::Right,
+            CompareFocus::Right => CompareFocus::Paths,
+        });
+    }
+
+    fn select_row(&mut self, row: usize) {
+        self.state.select(Some(row));
+        self.select_path();
+    }
+
+    pub fn next(&mut self) {
+        let len = self.comparison.paths.len();
+        match self.focus {
+            CompareFocus::Paths if len > 0 => {
+                self.select_row((self.state.selected().unwrap_or(0) + 1) % len)
+            }
+            CompareFocus::Paths => {}
+            CompareFocus::Left => self.left_segments.next(),
+            CompareFocus::Right => self.right_segments.next(),
+        }
+    }
+
+    pub fn previous(&mut self) {
+        let len = self.comparison.paths.len();
+        match self.focus {
+            CompareFocus::Paths if len > 0 => {
+                let row = self.state.selected().unwrap_or(0);
+                self.select_row(if row == 0 { len - 1 } else { row - 1 })
+            }
+            CompareFocus::Paths => {}
+            CompareFocus::Left => self.left_segments.previous(),
+            CompareFocus::Right => self.right_segments.previous(),
+        }
+    }
+
+    pub fn go_top(&mut self) {
+        match self.focus {
+            CompareFocus::Paths => self.select_row(0),
+            CompareFocus::Left => self.left_segments.go_top(),
+            CompareFocus::Right => self.right_segments.go_top(),
+        }
+    }
+
+    pub fn go_bottom(&mut self) {
+        match self.focus {
+            CompareFocus::Paths => self.select_row(self.comparison.paths.len().saturating_sub(1)),
+            CompareFocus::Left => self.left_segments.go_bottom(),
+            CompareFocus::Right => self.right_segments.go_bottom(),
+        }
+    }
+
+    fn render_compare_widget(&mut self, layout: Rect, frame: &mut Frame) {
+        let compare_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Percentage(60), Constraint::Fill(1)])
+            .split(layout);
+        let segment_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Percentage(50); 2])
+            .split(compare_layout[1]);
+        frame.render_widget(&mut *self, compare_layout[0]);
+        let (left, right) = match self.selected() {
+            Some(path) => (path.left, path.right),
+            None => (None, None),
+        };
+        // A path only one process maps has no segments on the other side.
+        for (idx, segments, name, layout) in [
+            (
+                left,
+                &mut self.left_segments,
+                &self.left_name,
+                segment_layout[0],
+            ),
+            (
+                right,
+                &mut self.right_segments,
+                &self.right_name,
+                segment_layout[1],
+            ),
+        ] {
+            match idx {
+                Some(idx) => segments.render_memory_widget(layout, frame, Some(idx)),
+                None => frame.render_widget(
+                    Paragraph::new("not mapped")
+                        .alignment(Alignment::Center)
+                        .block(
+                            Block::bordered()
+                                .title(name.clone())
+                                .title_alignment(Alignment::Center),
+                        ),
+                    layout,
+                ),
+            }
+        }
+    }
+}
+
+impl Widget for &mut CompareWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let rows: Vec<Row> = self
+            .comparison
+            .paths
+            .iter()
+            .map(|path| {
+                let (left, right) = (path.left_totals, path.right_totals);
+                Row::new([
+                    format!("{} {}", path.status.symbol(), path.path),
+                    format_size(left.rss, DECIMAL),
+                    format_size(right.rss, DECIMAL),
+                    format_delta(path.rss_delta()),
+                    format_size(left.pss, DECIMAL),
+                    format_size(right.pss, DECIMAL),
+                    format_delta(path.pss_delta()),
+                    format_size(left.private, DECIMAL),
+                    format_size(right.private, DECIMAL),
+                    format_delta(path.private_delta()),
+                ])
+                .style(diff_status_color(path.status))
+            })
+            .collect();
+        let mut widths = vec![Constraint::Fill(1)];
+        widths.extend([Constraint::Length(11); 9]);
+        let header = [
+            "Path",
+            "A RSS",
+            "B RSS",
+            "ΔRSS",
+            "A PSS",
+            "B PSS",
+            "ΔPSS",
+            "A Private",
+            "B Private",
+            "ΔPrivate",
+        ];
+        let title = format!(
+            "Compare A {} with B {} (- only in A, + only in B)",
+            self.left_name, self.right_name
+        );
+        let table = Table::new(rows, widths)
+            .block(
+                Block::bordered()
+                    .title_top(title)
+                    .title_alignment(Alignment::Center)
+                    .title_bottom(
+                        Line::from("j/k - move   tab - pane   c/ESC - close   q - quit").centered(),
+                    )
+                    .border_style(selected_pane_color(&(self.focus == CompareFocus::Paths))),
+            )
+            .highlight_style(Style::new().light_yellow())
+            .header(Row::new(header).style(Style::new().bold()));
+        StatefulWidget::render(table, area, buf, &mut self.state)
+    }
+}
+
+/// Every process mapping a file, opened from the path list to see who else
+/// pays for a library.
+pub struct WhoMapsWidget {
+    /// The path the file was selected under.
+    label: String,
+    target: FileTarget,
+    users: Vec<FileUser>,
+    state: TableState,
+    message: Option<String>,
+}
+
+impl WhoMapsWidget {
+    pub fn new(label: String, target: FileTarget) -> Self {
+        let mut widget = Self {
+            label,
+            target,
+            users: Vec::new(),
+            state: TableState::default(),
+            message: None,
+        };
+        widget.reload();
+        widget
+    }
+
+    /// Scan every process again.
+    pub fn reload(&mut self) {
+        self.users = whomaps::who_maps(&self.target);
+        self.state
+            .select(if self.users.is_empty() { None } else { Some(0) });
+    }
+
+    pub fn next(&mut self) {
+        if let Some(row) = self.state.selected() {
+            self.state.select(Some((row + 1) % self.users.len()));
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if let Some(row) = self.state.selected() {
+            let len = self.users.len();
+            self.state.select(Some((row + len - 1) % len));
+        }
+    }
+
+    pub fn go_top(&mut self) {
+        if !self.users.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    pub fn go_bottom(&mut self) {
+        if !self.users.is_empty() {
+            self.state.select(Some(self.users.len() - 1));
+        }
+    }
+
+    pub fn selected_pid(&self) -> Option<i32> {
+        Some(self.users.get(self.state.selected()?)?.pid)
+    }
+
+    pub fn message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+}
+
+impl Widget for &mut WhoMapsWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let rows: Vec<Row> = self
+            .users
+            .iter()
+            .map(|user| {
+                Row::new([
+                    user.pid.to_string(),
+                    user.comm.clone(),
+                    user.mappings.len().to_string(),
+                    format_size(user.rss(), DECIMAL),
+                    format_size(user.pss(), DECIMAL),
+                    user.path.clone(),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Length(8),
+            Constraint::Length(16),
+            Constraint::Length(9),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Fill(1),
+        ];
+        // The Pss of every process adds up to what the file costs the
+        // system, their Rss would count the shared pages several times.
+        let pss: u64 = self.users.iter().map(FileUser::pss).sum();
+        let title = format!(
+            "Processes mapping {} ({}, pss {})",
+            self.label,
+            self.users.len(),
+            format_size(pss, DECIMAL)
+        );
+        let keys = match &self.message {
+            Some(message) => Line::styled(message.clone(), Style::default().fg(Color::Red)),
+            None => Line::from("j/k - move   enter - open   r - rescan   w/ESC - close"),
+        };
+        let table = Table::new(rows, widths)
+            .header(
+                Row::new(["pid", "comm", "mappings", "rss", "pss", "path"])
+                    .style(Style::new().bold()),
+            )
+            .block(
+                Block::bordered()
+                    .title_top(title)
+                    .title_alignment(Alignment::Center)
+                    .title_bottom(keys.centered())
+                    .border_style(selected_pane_color(&true)),
+            )
+            .highlight_style(Style::new().light_yellow());
+        StatefulWidget::render(table, area, buf, &mut self.state)
+    }
+}
+
+/// The threads of a process with the Rss and size of their stacks, to open
+/// one of them in a tab.
+pub struct ThreadsWidget {
+    pid: i32,
+    /// What the tab of the process is called.
+    name: String,
+    threads: Vec<ThreadStack>,
+    state: TableState,
+    message: Option<String>,
+}
+
+impl ThreadsWidget {
+    pub fn new(pid: i32, name: String, threads: Vec<ThreadStack>) -> Self {
+        let selected = if threads.is_empty() { None } else { Some(0) };
+        Self {
+            pid,
+            name,
+            threads,
+            state: TableState::default().with_selected(selected),
+            message: None,
+        }
+    }
+
+    pub fn next(&mut self) {
+        if let Some(row) = self.state.selected() {
+            self.state.select(Some((row + 1) % self.threads.len()));
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if let Some(row) = self.state.selected() {
+            let len = self.threads.len();
+            self.state.select(Some((row + len - 1) % len));
+        }
+    }
+
+    pub fn go_top(&mut self) {
+        if !self.threads.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    pub fn go_bottom(&mut self) {
+        if !self.threads.is_empty() {
+            self.state.select(Some(self.threads.len() - 1));
+        }
+    }
+
+    /// The pid and tid of the selected thread.
+    pub fn selected(&self) -> Option<(i32, i32)> {
+        Some((self.pid, self.threads.get(self.state.selected()?)?.tid))
+    }
+
+    pub fn message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+}
+
+impl Widget for &mut ThreadsWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let rows: Vec<Row> = self
+            .threads
+            .iter()
+            .map(|thread| {
+                // The stack of a running thread can't be told.
+                let (size, rss) = match thread.stack {
+                    Some(_) => (
+                        format_size(thread.size, DECIMAL),
+                        format_size(thread.rss, DECIMAL),
+                    ),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                Row::new([thread.tid.to_string(), thread.name.clone(), size, rss])
+            })
+            .collect();
+        let widths = [
+            Constraint::Length(8),
+            Constraint::Length(16),
+            Constraint::Length(11),
+            Constraint::Fill(1),
+        ];
+        let keys = match &self.message {
+            Some(message) => Line::styled(message.clone(), Style::default().fg(Color::Red)),
+            None => Line::from("j/k - move   enter - open   H/ESC - close"),
+        };
+        let table = Table::new(rows, widths)
+            .header(Row::new(["tid", "name", "stack size", "stack rss"]).style(Style::new().bold()))
+            .block(
+                Block::bordered()
+                    .title_top(format!("Threads of {}", self.name))
+                    .title_alignment(Alignment::Center)
+                    .title_bottom(keys.centered())
+                    .border_style(selected_pane_color(&true)),
+            )
+            .highlight_style(Style::new().light_yellow());
+        StatefulWidget::render(table, area, buf, &mut self.state)
+    }
+}
+
+/// The `:` prompt.
+#[derive(Clone, Debug, Default)]
+pub struct CommandWidget {
+    pub toggle: bool,
+    pub input: String,
+    /// Outcome of the last command, and whether it failed.
+    message: Option<(String, bool)>,
+}
+
+impl CommandWidget {
+    fn render_command_widget(&self, layout: Rect, frame: &mut Frame) {
+        frame.render_widget(self, layout);
+    }
+
+    pub fn toggle(&mut self) {
+        self.toggle = !self.toggle;
+        self.input.clear();
+        self.message = None;
+    }
+
+    pub fn message(&mut self, message: String, error: bool) {
+        self.message = Some((message, error));
+    }
+}
+
+impl Widget for &CommandWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let term_block = Block::default()
+            .title("Command (goto <address>)")
+            .borders(Borders::ALL);
+        let mut text = Text::from(format!(":{}", self.input));
+        if let Some((message, error)) = &self.message {
+            let style = match error {
+                true => Style::default().fg(Color::Red),
+                false => Style::default(),
+            };
+            text.extend(Text::styled(message.clone(), style));
+        }
+        let term_text = Paragraph::new(text).block(term_block);
+        Clear.render(area, buf);
+        Widget::render(term_text, area, buf);
+    }
+}
+
+/// The list of running processes shown when no pid was given.
+pub struct ProcessPickerWidget {
+    /// Sorted by `order`, the searcher only filters them.
+    processes: Vec<ProcessSummary>,
+    /// Set when started with `--name` or `--match`, only the processes
+    /// matching it are listed.
+    matching: ProcessMatch,
+    order: ProcessOrder,
+    /// Pid of the highlighted process, kept across filtering and sorting.
+    selected: Option<i32>,
+    state: TableState,
+    searcher: Nucleo<usize>,
+    pub filter: String,
+    /// Why the last pick could not be opened.
+    message: Option<String>,
+    picked: Option<i32>,
+    running: bool,
+}
+
+impl ProcessPickerWidget {
+    pub fn new(matching: ProcessMatch) -> Self {
+        let num_threads = Some(available_parallelism().unwrap().get());
+        let searcher = Nucleo::new(Config::DEFAULT, Arc::new(|| {}), num_threads, 1);
+        let mut picker = Self {
+            processes: Vec::new(),
+            matching,
+            order: ProcessOrder::default(),
+            selected: None,
+            state: TableState::default(),
+            searcher,
+            filter: String::new(),
+            message: None,
+            picked: None,
+            running: true,
+        };
+        picker.reload();
+        picker
+    }
+
+    /// Read the process list again.
+    pub fn reload(&mut self) {
+        self.processes = processes::processes(&self.matching);
+        self.order.sort(&mut self.processes);
+        self.reload_searcher();
+        if self.selected.is_none() {
+            self.selected = self.processes.first().map(|p| p.pid);
+        }
+    }
+
+    fn reload_searcher(&mut self) {
+        self.searcher.restart(true);
+        for (idx, process) in self.processes.iter().enumerate() {
+            let text = format!("{} {} {}", process.pid, process.comm, process.cmdline);
+            self.searcher.injector().push(idx, |_, c| {
+                c[0] = Utf32String::from(text.as_str());
+            });
+        }
+        self.searcher.tick(10);
+    }
+
+    pub fn tick(&mut self) {
+        self.searcher.tick(10);
+    }
+
+    pub fn cycle_order(&mut self) {
+        self.order = self.order.next();
+        self.order.sort(&mut self.processes);
+        self.reload_searcher();
+    }
+
+    /// Index into `processes` of every visible row. Rows stay in the
+    /// picked order rather than nucleo's ranking.
+    fn rows(&self) -> Vec<usize> {
+        let snapshot = self.searcher.snapshot();
+        let mut rows: Vec<usize> = snapshot
+            .matched_items(0..snapshot.matched_item_count())
+            .map(|item| *item.data)
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    fn select_row(&mut self, row: usize) {
+        if let Some(idx) = self.rows().get(row) {
+            self.selected = Some(self.processes[*idx].pid);
+            self.state.select(Some(row));
+        }
+    }
+
+    pub fn next(&mut self) {
+        let len = self.rows().len();
+        if let (Some(v), true) = (self.state.selected(), len > 0) {
+            self.select_row((v + 1) % len);
+        };
+    }
+
+    pub fn previous(&mut self) {
+        let len = self.rows().len();
+        if let (Some(v), true) = (self.state.selected(), len > 0) {
+            let idx = if v == 0 { len - 1 } else { v - 1 };
+            self.select_row(idx);
+        };
+    }
+
+    pub fn go_top(&mut self) {
+        self.select_row(0);
+    }
+
+    pub fn go_bottom(&mut self) {
+        let len = self.rows().len();
+        self.select_row(len.saturating_sub(1));
+    }
+
+    /// Open the highlighted process.
+    pub fn pick(&mut self) {
+        self.picked = self.selected;
+    }
+
+    /// The pid picked since the last call.
+    pub fn take_picked(&mut self) -> Option<i32> {
+        self.picked.take()
+    }
+
+    pub fn message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub fn quit(&mut self) {
+        self.running = false;
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+}
+
+impl Widget for &mut ProcessPickerWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Fill(1)])
+            .split(area);
+
+        let mut lines = vec![Line::from(self.filter.clone())];
+        if let Some(message) = &self.message {
+            lines.push(Line::styled(
+                message.clone(),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        let filter_block = Block::bordered().title("Search for Process");
+        Paragraph::new(lines)
+            .block(filter_block)
+            .render(layout[0], buf);
+
+        self.searcher.pattern.reparse(
+            0,
+            &self.filter,
+            CaseMatching::Ignore,
+            Normalization::Never,
+            false,
+        );
+        let rows = self.rows();
+        match self
+            .selected
+            .and_then(|pid| rows.iter().position(|idx| self.processes[*idx].pid == pid))
+        {
+            Some(row) => self.state.select(Some(row)),
+            None if rows.is_empty() => self.state.select(None),
+            None => {
+                self.selected = Some(self.processes[rows[0]].pid);
+                self.state.select(Some(0));
+            }
+        }
+
+        let table_rows: Vec<Row> = rows
+            .iter()
+            .map(|idx| {
+                let process = &self.processes[*idx];
+                Row::new([
+                    process.pid.to_string(),
+                    process.comm.clone(),
+                    format_size(process.rss, DECIMAL),
+                    format_size(process.pss, DECIMAL),
+                    process.cmdline.clone(),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Length(8),
+            Constraint::Length(16),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Fill(1),
+        ];
+        let table = Table::new(table_rows, widths)
+            .header(Row::new(["pid", "comm", "rss", "pss", "cmdline"]).style(Style::new().bold()))
+            .block(
+                Block::bordered()
+                    .title(match self.matching.is_empty() {
+                        true => format!("Processes (by {})", self.order.name()),
+                        false => format!(
+                            "Processes matching {} (by {})",
+                            self.matching,
+                            self.order.name()
+                        ),
+                    })
+                    .title_alignment(Alignment::Center)
+                    .title_bottom(
+                        Line::from(
+                            "↑/↓ - move   enter - open   tab - sort   ctrl-r - reload   ESC - quit",
+                        )
+                        .centered(),
+                    )
+                    .border_style(selected_pane_color(&true)),
+            )
+            .highlight_style(Style::new().light_yellow());
+        StatefulWidget::render(table, layout[1], buf, &mut self.state)
+    }
+}
+
+#[derive(Default)]
+pub struct HelpWidget {
+    toggle: bool,
+    toggle_vm_flags: bool,
+}
+
+impl HelpWidget {
+    fn render_help_widget(&self, layout: Rect, frame: &mut Frame) {
+        frame.render_widget(self, layout)
+    }
+
+    pub fn toggle(&mut self) {
+        // If HelpWidget is being disabled, then also disable vm_flags to avoid confusion.
+        if self.toggle {
+            self.toggle_vm_flags = false;
+        }
+        self.toggle = !self.toggle;
+    }
+
+    pub fn toggle_vm_flags(&mut self) {
+        // If HelpWidget is not active, do not activate vm_flags screen to avoid confusion.
         if !self.toggle {
             return;
         }
@@ -579,11 +2593,70 @@ fn selected_pane_color(active_pane: &bool) -> Style {
     }
 }
 
-pub fn render(app: &mut App, frame: &mut Frame) {
-    let base_layout = Layout::default()
+fn diff_status_color(status: DiffStatus) -> Style {
+    match status {
+        DiffStatus::Added => Style::default().fg(Color::Green),
+        DiffStatus::Removed => Style::default().fg(Color::Red),
+        DiffStatus::Changed => Style::default().fg(Color::Yellow),
+        DiffStatus::Unchanged => Style::default(),
+    }
+}
+
+fn format_delta(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(delta.unsigned_abs(), DECIMAL))
+}
+
+/// Render the tab on screen, with the tab bar on top once there are several,
+/// or the process picker over everything while it is open.
+pub fn render(session: &mut Session, frame: &mut Frame) {
+    if let Some(picker) = session.picker.as_mut() {
+        frame.render_widget(picker, frame.size());
+        return;
+    }
+    let tabs_height = if session.apps.len() > 1 { 1 } else { 0 };
+    let layout = Layout::default()
         .direction(Direction::Vertical)
-        .constraints(vec![Constraint::Fill(1), Constraint::Length(3)])
+        .constraints(vec![Constraint::Length(tabs_height), Constraint::Fill(1)])
         .split(frame.size());
+    if session.apps.len() > 1 {
+        let titles = session
+            .apps
+            .iter()
+            .enumerate()
+            .map(|(idx, app)| format!("{} {}", idx + 1, app.name));
+        let tabs = Tabs::new(titles)
+            .select(session.current)
+            .highlight_style(Style::new().light_yellow());
+        frame.render_widget(tabs, layout[0]);
+    }
+    if let Some(threads) = session.threads.as_mut() {
+        frame.render_widget(threads, layout[1]);
+    } else if let Some(who_maps) = session.who_maps.as_mut() {
+        frame.render_widget(who_maps, layout[1]);
+    } else if let Some(compare) = session.compare.as_mut() {
+        compare.render_compare_widget(layout[1], frame);
+    } else if let Some(app) = session.app() {
+        render_app(app, frame, layout[1]);
+    }
+}
+
+fn render_app(app: &mut App, frame: &mut Frame, area: Rect) {
+    app.path_list_widget
+        .filter(app.path_filter_widget.filter.clone());
+    let timeline_height = if app.timeline.is_some() { 3 } else { 0 };
+    let address_space_height = if app.address_space_view { 8 } else { 0 };
+    let pagemap_height = if app.pagemap_view { 10 } else { 0 };
+    let base_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![
+            Constraint::Fill(1),
+            Constraint::Length(address_space_height),
+            Constraint::Length(pagemap_height),
+            Constraint::Length(timeline_height),
+            Constraint::Length(app.legend_widget.height(area.width)),
+        ])
+        .split(area);
 
     let content_layout = Layout::default()
         .direction(Direction::Horizontal)
@@ -597,7 +2670,12 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     let legend_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints(vec![Constraint::Percentage(100)])
-        .split(base_layout[1]);
+        .split(base_layout[4]);
+
+    if let Some(timeline) = &app.timeline {
+        app.timeline_widget
+            .render_timeline_widget(base_layout[3], frame, timeline);
+    }
 
     let main_layout = if app.debug {
         Layout::default()
@@ -614,51 +2692,184 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     };
     let main_layout = main_layout.split(content_layout[0]);
 
+    // A cgroup gets its accounting above the info pane.
+    let cgroup_height = match &app.cgroup_stats {
+        Some(stats) => stats.memory_stat.len() as u16 + 6,
+        None => 0,
+    };
     let info_layout = Layout::default()
         .direction(Direction::Vertical)
-        .constraints(vec![Constraint::Percentage(100)])
+        .constraints(vec![Constraint::Length(cgroup_height), Constraint::Fill(1)])
         .split(content_layout[1]);
+    if let Some(stats) = &app.cgroup_stats {
+        app.cgroup_widget
+            .render_cgroup_widget(info_layout[0], frame, stats, &app.snapshot());
+    }
 
-    let selected_segment = app.segment_list_widget.selected_segment();
     let indices = app.path_list_widget.selected_identifiers();
+    // The segment table may be hidden behind the treemap, it still has to
+    // follow the path list for the info pane.
+    app.segment_list_widget.selected_identifier(indices);
+    let selected_segment = app.segment_list_widget.selected_segment();
+    let sharing = match app.pagemap_view {
+        true => {
+            app.pagemap_widget
+                .update(app.pid(), selected_segment.clone());
+            app.pagemap_widget
+                .render_pagemap_widget(base_layout[2], frame);
+            app.pagemap_widget.sharing.clone()
+        }
+        false => None,
+    };
     if app.debug {
         app.info_widget
-            .render_info_widget(info_layout[0], frame, selected_segment);
-        app.segment_list_widget
-            .render_memory_widget(main_layout[0], frame, indices);
+            .render_info_widget(info_layout[1], frame, selected_segment, sharing);
+        if app.treemap_view {
+            app.treemap_widget
+                .render_treemap_widget(main_layout[0], frame, indices);
+        } else {
+            app.segment_list_widget
+                .render_memory_widget(main_layout[0], frame, indices);
+        }
         app.log_widget.render_log_widget(main_layout[1], frame);
-        app.path_list_widget.render_list_widget(
-            main_layout[2],
-            frame,
-            app.path_filter_widget.filter.clone(),
-        );
+        if app.tree_view {
+            app.tree_widget.render_tree_widget(main_layout[2], frame);
+        } else {
+            app.path_list_widget
+                .render_list_widget(main_layout[2], frame);
+        }
+        if app.address_space_view {
+            app.address_space_widget.render_address_space_widget(
+                base_layout[1],
+                frame,
+                app.segment_list_widget.selected_indexes(),
+            );
+        }
         app.legend_widget
             .render_legend_widget(legend_layout[0], frame);
         if app.path_list_widget.toggle {
-            app.path_filter_widget
-                .render_path_filter_widget(main_layout[0], frame);
+            app.path_filter_widget.render_path_filter_widget(
+                main_layout[0],
+                frame,
+                app.path_list_widget.filter_error(),
+            );
+        }
+        if app.command_widget.toggle {
+            app.command_widget
+                .render_command_widget(main_layout[0], frame);
         }
         if app.help_widget.toggle {
             app.help_widget.render_help_widget(content_layout[0], frame);
         }
     } else {
         app.info_widget
-            .render_info_widget(info_layout[0], frame, selected_segment);
-        app.segment_list_widget
-            .render_memory_widget(main_layout[0], frame, indices);
-        app.path_list_widget.render_list_widget(
-            main_layout[1],
-            frame,
-            app.path_filter_widget.filter.clone(),
-        );
+            .render_info_widget(info_layout[1], frame, selected_segment, sharing);
+        if app.treemap_view {
+            app.treemap_widget
+                .render_treemap_widget(main_layout[0], frame, indices);
+        } else {
+            app.segment_list_widget
+                .render_memory_widget(main_layout[0], frame, indices);
+        }
+        if app.tree_view {
+            app.tree_widget.render_tree_widget(main_layout[1], frame);
+        } else {
+            app.path_list_widget
+                .render_list_widget(main_layout[1], frame);
+        }
+        if app.address_space_view {
+            app.address_space_widget.render_address_space_widget(
+                base_layout[1],
+                frame,
+                app.segment_list_widget.selected_indexes(),
+            );
+        }
         app.legend_widget
             .render_legend_widget(legend_layout[0], frame);
         if app.path_list_widget.toggle {
-            app.path_filter_widget
-                .render_path_filter_widget(main_layout[0], frame);
+            app.path_filter_widget.render_path_filter_widget(
+                main_layout[0],
+                frame,
+                app.path_list_widget.filter_error(),
+            );
+        }
+        if app.command_widget.toggle {
+            app.command_widget
+                .render_command_widget(main_layout[0], frame);
         }
         if app.help_widget.toggle {
             app.help_widget.render_help_widget(content_layout[0], frame);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::SmapsSource;
+
+    #[test]
+    fn squarify_tiles_the_area_in_proportion() {
+        let area = Rect::new(3, 2, 60, 20);
+        let values = [500, 250, 120, 80, 40, 10];
+        let rects = squarify(&values, area);
+        assert_eq!(rects.len(), values.len());
+
+        // Every cell of the area is covered by exactly one tile.
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let covering = rects
+                    .iter()
+                    .filter(|rect| rect.contains(Position::new(x, y)))
+                    .count();
+                assert_eq!(covering, 1, "cell {}, {}", x, y);
+            }
+        }
+
+        // Rounding the edges moves each of them by half a cell at most.
+        let total: u64 = values.iter().sum();
+        for (rect, value) in rects.iter().zip(values) {
+            assert!(area.contains(rect.as_position()));
+            let expected = area.area() as f64 * value as f64 / total as f64;
+            let slack = (rect.width + rect.height) as f64;
+            assert!(
+                (rect.area() as f64 - expected).abs() <= slack,
+                "{:?} for {}",
+                rect,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn address_space_cells_fit_the_width() {
+        // Small mappings far apart, so the holes outweigh them.
+        let smaps = "\
+7f0000000000-7f0000001000 r--p 00000000 fd:01 1234 /usr/lib/libc.so.6
+Rss: 4 kB
+7f1000000000-7f1000001000 r-xp 00001000 fd:01 1234 /usr/lib/libc.so.6
+Rss: 4 kB
+7f2000000000-7f2040000000 rw-p 00000000 00:00 0 [heap]
+Rss: 4 kB
+7fff00000000-7fff00001000 rw-p 00000000 00:00 0 [stack]
+Rss: 4 kB
+";
+        let memory_maps = SmapsSource::Text(smaps.to_string()).load().unwrap();
+        let mut widget = AddressSpaceWidget::new(Rc::new(memory_maps));
+        for selected in 0..4 {
+            widget.selected = Some(selected);
+            for total in 0..20 {
+                let cells = widget.cells(total);
+                let used: usize = cells.iter().map(|(h, m)| h + m).sum();
+                assert!(used <= total, "{:?} in {}", cells, total);
+                assert!(total == 0 || cells[selected].1 > 0, "{:?}", cells);
+            }
+        }
+    }
+
+    #[test]
+    fn squarify_without_weight() {
+        let rects = squarify(&[0, 0], Rect::new(0, 0, 10, 10));
+        assert_eq!(rects, [Rect::default(); 2]);
+    }
+}
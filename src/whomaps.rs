@@ -0,0 +1,166 @@
+use crate::app::{mmpath_to_string, AppResult, Metric};
+use humansize::{format_size, DECIMAL};
+use procfs::process::MMapPath;
+use procfs::process::MemoryMap;
+use std::cmp::Reverse;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The file to look for in every process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileTarget {
+    /// Matched on the file the path names, see [`FileTarget::resolve`].
+    Path(PathBuf),
+    /// Matched on the device (major, minor) and inode, which also finds the
+    /// file under another path, e.g. in a container, or once deleted.
+    Inode((i32, i32), u64),
+}
+
+impl FromStr for FileTarget {
+    type Err = String;
+
+    /// `major:minor:inode` with the device in hex as in smaps, e.g.
+    /// `fd:01:1835023`, or else a path.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if let [major, minor, inode] = parts.as_slice() {
+            if let (Ok(major), Ok(minor), Ok(inode)) = (
+                i32::from_str_radix(major, 16),
+                i32::from_str_radix(minor, 16),
+                inode.parse(),
+            ) {
+                return Ok(FileTarget::Inode((major, minor), inode));
+            }
+        }
+        Ok(FileTarget::Path(PathBuf::from(s)))
+    }
+}
+
+impl FileTarget {
+    /// The file behind a mapping, if it is backed by one.
+    pub fn of(mm: &MemoryMap) -> Option<Self> {
+        match mm.pathname {
+            MMapPath::Path(_) if mm.inode != 0 => Some(FileTarget::Inode(mm.dev, mm.inode)),
+            _ => None,
+        }
+    }
+
+    /// The targets to look for: a path stands for its canonical path as
+    /// well as the device and inode of its file, so symlinks and other
+    /// paths to the same file are found too. The canonical path also finds
+    /// the file on overlayfs, where the device in smaps is the one of the
+    /// layer below. A path that doesn't exist is looked for as it is.
+    fn resolve(&self) -> Vec<FileTarget> {
+        let FileTarget::Path(path) = self else {
+            return vec![self.clone()];
+        };
+        let mut targets = vec![FileTarget::Path(
+            fs::canonicalize(path).unwrap_or_else(|_| path.clone()),
+        )];
+        if let Ok(metadata) = fs::metadata(path) {
+            targets.push(FileTarget::Inode(split_dev(metadata.dev()), metadata.ino()));
+        }
+        targets
+    }
+
+    fn matches(&self, mm: &MemoryMap) -> bool {
+        match self {
+            FileTarget::Path(path) => mm.pathname == MMapPath::Path(path.clone()),
+            FileTarget::Inode(dev, inode) => {
+                matches!(mm.pathname, MMapPath::Path(_)) && mm.dev == *dev && mm.inode == *inode
+            }
+        }
+    }
+}
+
+/// The major and minor number of a device as encoded by the kernel in
+/// `st_dev`.
+fn split_dev(dev: u64) -> (i32, i32) {
+    let major = ((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0xfff);
+    let minor = ((dev >> 12) & 0xffff_ff00) | (dev & 0xff);
+    (major as i32, minor as i32)
+}
+
+/// A process mapping the file, with the fields of its mappings of it added
+/// up.
+#[derive(Clone, Debug)]
+pub struct FileUser {
+    pub pid: i32,
+    pub comm: String,
+    /// The path the process maps the file under.
+    pub path: String,
+    pub mappings: Vec<MemoryMap>,
+}
+
+impl FileUser {
+    pub fn rss(&self) -> u64 {
+        Metric::Rss.sum(&self.mappings)
+    }
+
+    pub fn pss(&self) -> u64 {
+        Metric::Pss.sum(&self.mappings)
+    }
+}
+
+/// Every readable process mapping `target`, largest Pss first. This reads
+/// the smaps of every process, so it takes a moment on a busy host.
+pub fn who_maps(target: &FileTarget) -> Vec<FileUser> {
+    let Ok(all) = procfs::process::all_processes() else {
+        return Vec::new();
+    };
+    let targets = target.resolve();
+    let mut users: Vec<FileUser> = all
+        .flatten()
+        .filter_map(|process| {
+            let mappings: Vec<MemoryMap> = process
+                .smaps()
+                .ok()?
+                .into_iter()
+                .filter(|mm| targets.iter().any(|target| target.matches(mm)))
+                .collect();
+            let first = mappings.first()?;
+            Some(FileUser {
+                pid: process.pid,
+                comm: process.stat().map(|stat| stat.comm).unwrap_or_default(),
+                path: mmpath_to_string(&first.pathname),
+                mappings,
+            })
+        })
+        .collect();
+    users.sort_by_key(|user| Reverse(user.pss()));
+    users
+}
+
+/// Print the processes mapping `target` and their total.
+pub fn report(target: &FileTarget, mut out: impl Write) -> AppResult<()> {
+    let users = who_maps(target);
+    writeln!(
+        out,
+        "{:>8}  {:<16}{:>10}{:>12}{:>12}  PATH",
+        "PID", "COMM", "MAPPINGS", "RSS", "PSS"
+    )?;
+    for user in users.iter() {
+        writeln!(
+            out,
+            "{:>8}  {:<16}{:>10}{:>12}{:>12}  {}",
+            user.pid,
+            user.comm,
+            user.mappings.len(),
+            format_size(user.rss(), DECIMAL),
+            format_size(user.pss(), DECIMAL),
+            user.path
+        )?;
+    }
+    // Pss adds up to what the file costs the system, Rss would count the
+    // shared pages once per process.
+    writeln!(
+        out,
+        "{} processes, pss {}",
+        users.len(),
+        format_size(users.iter().map(FileUser::pss).sum::<u64>(), DECIMAL)
+    )?;
+    Ok(())
+}